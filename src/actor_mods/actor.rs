@@ -5,14 +5,22 @@ use ggez::{Context, GameResult};
 use oorandom::Rand32;
 use std::str::FromStr;
 
-use crate::assets::Assets;
-use crate::vector2::{random_vec, vec_from_angle, Vector2};
+use crate::assets::{Assets, Sprite};
+use crate::collision_mods::hitbox::Hitbox;
+use crate::pattern_mods::pattern_runner::PatternRunner;
+use crate::vector2::{normalize_angle, random_vec, vec_from_angle, Vector2};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ActorType {
     Player,
     Bullet,
     Enemy,
+    // フェーズごとに体力とパターンが切り替わる敵
+    Boss,
+    // 打ち消した弾から出る得点アイテム
+    Item,
+    // 当たり判定を持たない爆発などの演出
+    Effect,
     Other,
 }
 
@@ -29,19 +37,70 @@ impl FromStr for ActorType {
             "Player" => Ok(ActorType::Player),
             "Bullet" => Ok(ActorType::Bullet),
             "Enemy" => Ok(ActorType::Enemy),
+            "Boss" => Ok(ActorType::Boss),
+            "Item" => Ok(ActorType::Item),
+            "Effect" => Ok(ActorType::Effect),
             "Other" => Ok(ActorType::Other),
             _ => Err("Error"),
         }
     }
 }
 
+// 弾の種類
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BulletKind {
+    Normal,
+    // anchor から facing の方向に length だけ伸びる直線レーザー
+    // warn_time の間は予告線だけを表示し、当たり判定を持たない
+    // ang_vel を与えると anchor を中心に回転するビームになる
+    // anchor は撃った時点の位置のまま動かず、撃った敵が動いても付いていかない
+    Laser {
+        anchor: Vector2,
+        length: f32,
+        warn_time: f32,
+        duration: f32,
+    },
+    // delay 秒だけ遅れて動き出す節。同じ軌道をなぞる節を並べて曲がるレーザーを作る
+    SnakeSegment {
+        delay: f32,
+    },
+}
+
+impl Default for BulletKind {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+// 狙う相手へ向きを変え続ける誘導弾の性能
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Homing {
+    // 1 秒あたりに曲がれる角度の上限 [rad/s]
+    pub turn_rate: f32,
+    // 生成から誘導をやめるまでの時間 [s]。f32::INFINITY なら誘導し続ける
+    pub duration: f32,
+}
+
+// 得点アイテムの出どころ。打ち消しボーナスに数えるのは打ち消した弾から出たものだけ
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ItemOrigin {
+    // 打ち消した敵弾から出た
+    Cancel,
+    // 倒した敵が落とした
+    Drop,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Actor {
     tag: ActorType,
     x_y: Vector2,
+    // 直前のステップでの位置。高速な弾の当たり判定に用いる
+    prev_x_y: Vector2,
     w_h: Vector2,
+    hitbox: Hitbox,
     facing: f32,
     pub vel: Vector2,
+    // 1 秒あたりの回転 [rad/s]
     ang_vel: f32,
 
     // I am going to lazily overload "life" with a
@@ -51,6 +110,18 @@ pub struct Actor {
     life: i32,
     collision_timeout: f32,
     max_collision_timeout: f32,
+    // 弾が命中後も消えずに貫通できる残り回数
+    pierce: i32,
+    kind: BulletKind,
+    // 生成されてからの経過時間 [s]
+    age: f32,
+    // 弾自身が持つパターン。持っている弾の速度はパターンが決める
+    pattern: Option<PatternRunner>,
+    homing: Option<Homing>,
+    // 自機のすぐ近くをかすめたか。かすりは弾 1 つにつき一度だけ数える
+    grazed: bool,
+    // 得点アイテムの出どころ。アイテム以外は None
+    item_origin: Option<ItemOrigin>,
 }
 
 impl Actor {
@@ -67,16 +138,53 @@ impl Actor {
         Actor {
             tag: tag,
             x_y: x_y,
+            prev_x_y: x_y,
             w_h: w_h,
+            hitbox: Hitbox::from_w_h(w_h),
             facing: facing,
             vel: vel,
             ang_vel: ang_vel,
             life: life,
             collision_timeout: 0.0,
             max_collision_timeout: max_collision_timeout,
+            pierce: 0,
+            kind: BulletKind::Normal,
+            age: 0.0,
+            pattern: None,
+            homing: None,
+            grazed: false,
+            item_origin: None,
         }
     }
 
+    pub fn with_kind(mut self, kind: BulletKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_homing(mut self, turn_rate: f32, duration: f32) -> Self {
+        self.homing = Some(Homing {
+            turn_rate: turn_rate,
+            duration: duration,
+        });
+        self
+    }
+
+    pub fn with_item_origin(mut self, origin: ItemOrigin) -> Self {
+        self.item_origin = Some(origin);
+        self
+    }
+
+    pub fn with_hitbox(mut self, hitbox: Hitbox) -> Self {
+        self.hitbox = hitbox;
+        self
+    }
+
+    pub fn with_pierce(mut self, pierce: i32) -> Self {
+        self.pierce = pierce;
+        self
+    }
+
     pub fn get_tag(&self) -> ActorType {
         self.tag
     }
@@ -89,6 +197,10 @@ impl Actor {
         self.w_h
     }
 
+    pub fn get_hitbox(&self) -> Hitbox {
+        self.hitbox
+    }
+
     pub fn get_facing(&self) -> f32 {
         self.facing
     }
@@ -101,6 +213,65 @@ impl Actor {
         self.collision_timeout
     }
 
+    pub fn get_pierce(&self) -> i32 {
+        self.pierce
+    }
+
+    pub fn get_kind(&self) -> BulletKind {
+        self.kind
+    }
+
+    pub fn get_age(&self) -> f32 {
+        self.age
+    }
+
+    pub fn get_pattern(&self) -> Option<PatternRunner> {
+        self.pattern
+    }
+
+    pub fn get_item_origin(&self) -> Option<ItemOrigin> {
+        self.item_origin
+    }
+
+    pub fn get_homing(&self) -> Option<Homing> {
+        self.homing
+    }
+
+    pub fn is_homing(&self) -> bool {
+        match self.homing {
+            Some(homing) => self.age < homing.duration,
+            None => false,
+        }
+    }
+
+    pub fn set_pattern(&mut self, pattern: PatternRunner) {
+        self.pattern = Some(pattern);
+    }
+
+    // 予告中のレーザーや動き出す前の節は当たらない
+    pub fn is_lethal(&self) -> bool {
+        match self.kind {
+            BulletKind::Normal => true,
+            BulletKind::Laser { warn_time, .. } => self.age >= warn_time,
+            BulletKind::SnakeSegment { delay } => self.age >= delay,
+        }
+    }
+
+    pub fn is_laser(&self) -> bool {
+        match self.kind {
+            BulletKind::Laser { .. } => true,
+            _ => false,
+        }
+    }
+
+    // 曲がるレーザーの節は進行方向に沿わせる
+    pub fn hitbox_angle(&self) -> f32 {
+        match self.kind {
+            BulletKind::SnakeSegment { .. } => self.vel.angle(),
+            _ => self.facing,
+        }
+    }
+
     pub fn dec_life(&mut self, amount: i32) {
         // 当たり判定の確認
         if true
@@ -122,65 +293,137 @@ impl Actor {
         }
     }
 
-    pub fn draw(self, ctx: &mut Context, assets: &Assets, world_coords: (f32, f32)) -> GameResult {
-        let x_y = self.get_x_y().world_to_screen_coords(world_coords);
-        let x_y = na::Point2::new(x_y.0, x_y.1);
-        let image = assets.actor_image(self);
-        let drawparams = graphics::DrawParam::new()
-            .dest(x_y)
-            .rotation(self.get_facing() as f32)
-            .offset(na::Point2::new(0.5, 0.5));
+    pub fn consume_all(&mut self) {
+        self.life = 0;
+    }
 
-        graphics::draw(ctx, image, drawparams)?;
-        Ok(())
+    pub fn set_life(&mut self, life: i32) {
+        self.life = life;
+    }
+
+    // 命中した弾を消費する。貫通回数が残っていれば消えずに回数だけ減らす
+    pub fn consume(&mut self) {
+        if self.pierce > 0 {
+            self.pierce -= 1;
+        } else {
+            self.life = 0;
+        }
     }
 
-    pub fn draw_collision(
+    pub fn draw(self, ctx: &mut Context, assets: &Assets, world_coords: (f32, f32)) -> GameResult {
+        self.draw_sprite(ctx, assets.actor_sprite(self), world_coords)
+    }
+
+    pub fn draw_sprite(
         self,
         ctx: &mut Context,
-        color: graphics::Color,
+        sprite: &Sprite,
         world_coords: (f32, f32),
     ) -> GameResult {
-        let w_h = self.get_w_h();
+        let drawparams = self.sprite_param(sprite, world_coords);
+        graphics::draw(ctx, sprite.get_image(), drawparams)?;
+        Ok(())
+    }
+
+    // sprite を中心に合わせて向きどおりに描くための DrawParam。SpriteBatch にもそのまま渡せる
+    pub fn sprite_param(self, sprite: &Sprite, world_coords: (f32, f32)) -> graphics::DrawParam {
         let x_y = self.get_x_y().world_to_screen_coords(world_coords);
         let x_y = na::Point2::new(x_y.0, x_y.1);
-        let rect = graphics::Mesh::new_circle(
-            ctx,
-            graphics::DrawMode::stroke(2.0),
-            na::Point2::new(0.0, 0.0),
-            w_h.norm(),
-            1.0,
-            color,
-        )?;
-        let drawparams = graphics::DrawParam::new()
+        graphics::DrawParam::new()
             .dest(x_y)
             .rotation(self.get_facing() as f32)
-            .offset(na::Point2::new(0.5, 0.5));
+            .src(sprite.get_src())
+            .offset(na::Point2::new(0.5, 0.5))
+            .color(self.color())
+    }
 
-        graphics::draw(ctx, &rect, drawparams)?;
-        Ok(())
+    // レーザーは画像を使わず、原点を中心とした 1x1 の四角を線分の太さと長さに伸ばして描く
+    // 描かない間 (動き出す前の節) は None
+    pub fn beam_param(self, world_coords: (f32, f32)) -> Option<graphics::DrawParam> {
+        const WARN_WIDTH: f32 = 1.0;
+        let (half_len, radius) = match self.hitbox {
+            Hitbox::Capsule { half_len, radius } => (half_len, radius),
+            _ => (0.0, self.hitbox.bounding_radius()),
+        };
+        let (width, color) = match self.kind {
+            BulletKind::SnakeSegment { delay } if self.age < delay => return None,
+            BulletKind::Laser { warn_time, .. } if self.age < warn_time => {
+                (WARN_WIDTH, graphics::Color::new(1.0, 0.3, 0.3, 0.5))
+            }
+            _ => (radius * 2.0, graphics::Color::new(1.0, 0.6, 0.6, 1.0)),
+        };
+        let length = 2.0 * half_len.max(radius / 2.0);
+        let x_y = self.x_y.world_to_screen_coords(world_coords);
+        Some(
+            graphics::DrawParam::new()
+                .dest(na::Point2::new(x_y.0, x_y.1))
+                .rotation(self.hitbox_angle())
+                .scale(na::Vector2::new(width, length))
+                .color(color),
+        )
     }
 
     pub fn update_actor_position(&mut self, dt: f32) {
-        const MAX_PHYSICS_VEL: f32 = 150.0;
-        // Clamp the velocity to the max efficiently
-        let vel_norm = self.vel.norm();
-        if vel_norm > MAX_PHYSICS_VEL {
-            self.vel = self.vel / vel_norm * MAX_PHYSICS_VEL;
+        self.age += dt;
+        self.prev_x_y = self.x_y;
+        match self.kind {
+            BulletKind::Laser {
+                anchor,
+                length,
+                warn_time,
+                duration,
+            } => {
+                self.facing += self.ang_vel * dt;
+                self.x_y = anchor + vec_from_angle(self.facing) * (length / 2.0);
+                if self.age > warn_time + duration {
+                    self.life = 0;
+                }
+                return;
+            }
+            BulletKind::SnakeSegment { delay } if self.age < delay => return,
+            _ => (),
         }
-        if self.get_tag() != ActorType::Player {
-            self.rotate_actor_position();
+        const MAX_PHYSICS_VEL: f32 = 150.0;
+        if self.pattern.is_none() {
+            // Clamp the velocity to the max efficiently
+            // 得点アイテムは自機へ素早く吸い寄せたいので上限をかけない
+            let vel_norm = self.vel.norm();
+            if vel_norm > MAX_PHYSICS_VEL && self.tag != ActorType::Item {
+                self.vel = self.vel / vel_norm * MAX_PHYSICS_VEL;
+            }
+            // 向きと一緒に進む方向も回す。ang_vel を持つ敵や弾は曲がって進む
+            self.vel = self.vel.rotated(self.ang_vel * dt);
         }
         let dv = self.vel * dt;
         self.x_y += dv;
-        self.facing += self.ang_vel;
+        self.facing += self.ang_vel * dt;
+    }
+
+    // 経路に沿って x_y へ動かす。速度は移動量から求める
+    pub fn move_to(&mut self, x_y: Vector2, dt: f32) {
+        self.age += dt;
+        self.prev_x_y = self.x_y;
+        self.vel = (x_y - self.x_y) / dt;
+        self.x_y = x_y;
+        self.facing += self.ang_vel * dt;
     }
 
-    fn rotate_actor_position(&mut self) {
-        let vel_norm = self.vel.norm();
-        let unit_vel = self.vel / vel_norm;
-        let vel_after = unit_vel + vec_from_angle(self.facing);
-        self.vel = vel_after * vel_norm;
+    // 速さを変えずに target の方へ曲がる。1 ステップで曲がれるのは turn_rate * dt まで
+    pub fn steer_towards(&mut self, target: Vector2, dt: f32) {
+        let homing = match self.homing {
+            Some(homing) if self.is_homing() => homing,
+            _ => return,
+        };
+        if self.vel.norm_squared() == 0.0 {
+            return;
+        }
+        let desired = (target - self.x_y).angle();
+        let max_turn = homing.turn_rate * dt;
+        let turn = normalize_angle(desired - self.vel.angle())
+            .max(-max_turn)
+            .min(max_turn);
+        self.vel = self.vel.rotated(turn);
+        self.facing = self.vel.angle();
     }
 
     pub fn wrap_actor_position(&mut self, screen_w_h: Vector2) {
@@ -200,20 +443,107 @@ impl Actor {
         }
     }
 
+    // アイテムは弾から少しずつ浮かび上がるように現れる
+    fn color(&self) -> graphics::Color {
+        const ITEM_FADE_TIME: f32 = 0.3;
+        match self.tag {
+            ActorType::Item => {
+                let alpha = (self.age / ITEM_FADE_TIME).min(1.0);
+                graphics::Color::new(1.0, 0.9, 0.2, 0.3 + 0.7 * alpha)
+            }
+            _ => graphics::WHITE,
+        }
+    }
+
+    // パターンに跳ね返りの回数が残っていれば、画面端で位置と向きを折り返す
+    pub fn bounce_off_walls(&mut self, screen_w_h: Vector2) {
+        let mut pattern = match self.pattern {
+            Some(pattern) if pattern.get_bounces() > 0 => pattern,
+            _ => return,
+        };
+        let (x_bounds, y_bounds) = (screen_w_h.0 / 2.0, screen_w_h.1 / 2.0);
+        let horizontal = self.x_y.0.abs() > x_bounds && self.x_y.0 * self.vel.0 > 0.0;
+        let vertical = self.x_y.1.abs() > y_bounds && self.x_y.1 * self.vel.1 > 0.0;
+        if !horizontal && !vertical {
+            return;
+        }
+        if horizontal {
+            self.x_y.0 = (2.0 * x_bounds - self.x_y.0.abs()) * self.x_y.0.signum();
+        }
+        if vertical {
+            self.x_y.1 = (2.0 * y_bounds - self.x_y.1.abs()) * self.x_y.1.signum();
+        }
+        pattern.reflect(horizontal, vertical);
+        self.vel = pattern.velocity();
+        self.pattern = Some(pattern);
+    }
+
     pub fn inside_window(&self, screen_w_h: Vector2) -> bool {
         let (sx, sy) = (screen_w_h.0, screen_w_h.1);
         let (screen_x_bounds, screen_y_bounds) = (sx / 2.0, sy / 2.0);
         self.x_y.0.abs() < screen_x_bounds && self.x_y.1.abs() < screen_y_bounds
     }
 
-    pub fn handle_actor_collision(&mut self, bullet: Actor) {
-        let player_size = self.w_h.norm() / 2.0;
-        let pdistance = (bullet.x_y - self.x_y).norm();
-        let bullet_size = bullet.w_h.norm();
-        if pdistance < player_size + bullet_size && self.get_collision_timeout() < 0.0 {
+    // 速い弾は 1 ステップで小さな当たり判定をすり抜けてしまうので、
+    // 直前の位置から現在の位置までを掃いたカプセルとして扱う
+    fn swept_hitbox(&self) -> (Hitbox, Vector2, f32) {
+        const SWEEP_SPEED: f32 = 120.0;
+        if let (BulletKind::Normal, Hitbox::Circle { radius }) = (self.kind, self.hitbox) {
+            let dv = self.x_y - self.prev_x_y;
+            if self.vel.norm_squared() > SWEEP_SPEED * SWEEP_SPEED && dv.norm_squared() > 0.0 {
+                let swept = Hitbox::Capsule {
+                    half_len: dv.norm() / 2.0,
+                    radius: radius,
+                };
+                return (swept, self.prev_x_y + dv / 2.0, dv.angle());
+            }
+        }
+        (self.hitbox, self.x_y, self.hitbox_angle())
+    }
+
+    // broad phase で用いる、形全体を含む円の半径
+    pub fn bounding_radius(&self) -> f32 {
+        let (hitbox, x_y, _) = self.swept_hitbox();
+        hitbox.bounding_radius() + (x_y - self.x_y).norm()
+    }
+
+    // narrow phase
+    pub fn overlaps(&self, bullet: &Actor) -> bool {
+        let (bullet_hitbox, bullet_x_y, bullet_angle) = bullet.swept_hitbox();
+        self.hitbox.intersects(
+            self.x_y,
+            self.hitbox_angle(),
+            bullet_hitbox,
+            bullet_x_y,
+            bullet_angle,
+        )
+    }
+
+    // 弾が target から radius 以内をかすめたら、弾ごとに一度だけ true を返す
+    pub fn graze(&mut self, target: &Actor, radius: f32) -> bool {
+        if self.grazed || self.life <= 0 || !self.is_lethal() || target.life <= 0 {
+            return false;
+        }
+        let reach = radius + self.hitbox.bounding_radius();
+        if (self.x_y - target.x_y).norm_squared() < reach * reach {
+            self.grazed = true;
+            return true;
+        }
+        false
+    }
+
+    // 命中した場合は弾を消費して true を返す
+    pub fn handle_actor_collision(&mut self, bullet: &mut Actor) -> bool {
+        if self.get_life() <= 0 || bullet.get_life() <= 0 || !bullet.is_lethal() {
+            return false;
+        }
+        if self.overlaps(bullet) && self.get_collision_timeout() < 0.0 {
             self.set_collision_timeout(self.max_collision_timeout);
             self.dec_life(1);
+            bullet.consume();
+            return true;
         }
+        false
     }
 }
 
@@ -223,7 +553,7 @@ pub fn create_player() -> Actor {
     const PLAYER_HEIGHT: f32 = 8.0;
     Actor::new(
         ActorType::Player,
-        Vector2(0.0, -300.0),
+        Vector2(0.0, -180.0),
         Vector2(PLAYER_WIDTH, PLAYER_HEIGHT),
         0.0,
         Vector2(0.0, 0.0),
@@ -286,3 +616,178 @@ pub fn create_bullet(x_y: Vector2, w_h: Vector2, facing: f32, vel: Vector2, ang_
         f32::MAX,
     )
 }
+
+// 打ち消した弾や倒した敵の位置に出る得点アイテム。少し跳ねてから自機へ吸い寄せられる
+pub fn create_item(x_y: Vector2, origin: ItemOrigin) -> Actor {
+    const ITEM_SPEED: f32 = 240.0;
+    const ITEM_TURN_RATE: f32 = 10.0;
+    const ITEM_SIZE: f32 = 6.0;
+    Actor::new(
+        ActorType::Item,
+        x_y,
+        Vector2(ITEM_SIZE, ITEM_SIZE),
+        0.0,
+        Vector2(0.0, ITEM_SPEED),
+        0.0,
+        1,
+        0.0,
+    )
+    .with_homing(ITEM_TURN_RATE, f32::INFINITY)
+    .with_item_origin(origin)
+}
+
+// 敵が倒れた位置に出す爆発。アニメーションが終わったら消す
+pub fn create_explosion(x_y: Vector2) -> Actor {
+    const EXPLOSION_SIZE: f32 = 32.0;
+    Actor::new(
+        ActorType::Effect,
+        x_y,
+        Vector2(EXPLOSION_SIZE, EXPLOSION_SIZE),
+        0.0,
+        Vector2(0.0, 0.0),
+        0.0,
+        1,
+        0.0,
+    )
+}
+
+// turn_rate [rad/s] で相手を追いかける弾。duration 秒で誘導をやめて直進する
+pub fn create_homing_bullet(
+    x_y: Vector2,
+    facing: f32,
+    vel_norm: f32,
+    turn_rate: f32,
+    duration: f32,
+    size: f32,
+) -> Actor {
+    create_bullet(
+        x_y,
+        Vector2(size, size),
+        facing,
+        vec_from_angle(facing) * vel_norm,
+        0.0,
+    )
+    .with_homing(turn_rate, duration)
+}
+
+// anchor から facing の方向へ伸びるレーザー。ang_vel [rad/s] で anchor を中心に回る
+// anchor は固定で、撃った敵が動いてもその場に残る
+pub fn create_laser(
+    anchor: Vector2,
+    facing: f32,
+    length: f32,
+    width: f32,
+    warn_time: f32,
+    duration: f32,
+    ang_vel: f32,
+) -> Actor {
+    create_bullet(
+        anchor + vec_from_angle(facing) * (length / 2.0),
+        Vector2(width, length),
+        facing,
+        Vector2(0.0, 0.0),
+        ang_vel,
+    )
+    .with_hitbox(Hitbox::Capsule {
+        half_len: length / 2.0,
+        radius: width / 2.0,
+    })
+    .with_pierce(i32::MAX)
+    .with_kind(BulletKind::Laser {
+        anchor: anchor,
+        length: length,
+        warn_time: warn_time,
+        duration: duration,
+    })
+}
+
+// anchor を中心に等間隔に並び、ang_vel で回転するビーム
+pub fn create_rotating_beams(
+    anchor: Vector2,
+    num: i32,
+    length: f32,
+    width: f32,
+    warn_time: f32,
+    duration: f32,
+    ang_vel: f32,
+) -> Vec<Actor> {
+    let new_beam = |i| {
+        let r_angle = i as f32 / num as f32 * (2.0 * std::f32::consts::PI);
+        create_laser(anchor, r_angle, length, width, warn_time, duration, ang_vel)
+    };
+    (0..num).map(new_beam).collect()
+}
+
+// 先頭から interval 秒ずつ遅れて同じ軌道をなぞる節を並べた、曲がるレーザー
+pub fn create_snake_laser(
+    x_y: Vector2,
+    facing: f32,
+    vel_norm: f32,
+    ang_vel: f32,
+    segment_num: i32,
+    interval: f32,
+    width: f32,
+) -> Vec<Actor> {
+    let half_len = vel_norm * interval / 2.0;
+    let new_segment = |i| {
+        create_bullet(
+            x_y,
+            Vector2(width, half_len * 2.0),
+            facing,
+            vec_from_angle(facing) * vel_norm,
+            ang_vel,
+        )
+        .with_hitbox(Hitbox::Capsule {
+            half_len: half_len,
+            radius: width / 2.0,
+        })
+        .with_pierce(i32::MAX)
+        .with_kind(BulletKind::SnakeSegment {
+            delay: i as f32 * interval,
+        })
+    };
+    (0..segment_num).map(new_segment).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 秒進めたときのレーザーの向きと位置。ステップの細かさによらないはず
+    fn rotate_laser(steps: u32) -> (f32, Vector2) {
+        let anchor = Vector2(10.0, 20.0);
+        let mut laser = create_laser(anchor, 0.0, 100.0, 4.0, 0.0, 10.0, 0.5);
+        for _ in 0..steps {
+            laser.update_actor_position(1.0 / steps as f32);
+        }
+        (laser.get_facing(), laser.get_x_y())
+    }
+
+    #[test]
+    fn laser_turns_by_ang_vel_per_second_around_its_anchor() {
+        let (facing_60, x_y_60) = rotate_laser(60);
+        let (facing_30, x_y_30) = rotate_laser(30);
+        assert!((facing_60 - 0.5).abs() < 1e-4);
+        assert!((facing_30 - 0.5).abs() < 1e-4);
+        assert!((x_y_60 - x_y_30).norm() < 1e-3);
+        // 中心は anchor から向きの方向へ長さの半分
+        let expected = Vector2(10.0, 20.0) + vec_from_angle(0.5) * 50.0;
+        assert!((x_y_60 - expected).norm() < 1e-3);
+    }
+
+    #[test]
+    fn items_are_not_speed_capped() {
+        let mut item = create_item(Vector2(0.0, 0.0), ItemOrigin::Cancel);
+        let mut bullet = create_bullet(
+            Vector2(0.0, 0.0),
+            Vector2(4.0, 4.0),
+            0.0,
+            Vector2(0.0, 240.0),
+            0.0,
+        );
+        item.update_actor_position(1.0);
+        bullet.update_actor_position(1.0);
+        assert!((item.get_x_y().1 - 240.0).abs() < 1e-3);
+        assert!((bullet.get_x_y().1 - 150.0).abs() < 1e-3);
+    }
+}
@@ -1,21 +1,61 @@
 use ggez;
 use ggez::graphics;
-use ggez::{Context, GameResult};
+use ggez::GameResult;
 
 use crate::actor_mods::actor::*;
 use crate::assets::Assets;
-use crate::draw::draw_text;
 use crate::input::InputState;
+use crate::path_mods::path::Path;
+use crate::pattern_mods::pattern::PatternBook;
+use crate::pattern_mods::pattern_runner::{PatternContext, PatternRunner};
+use crate::render_mods::layer::Layer;
+use crate::render_mods::sprite_batch::SpriteRenderer;
 use crate::vector2::Vector2;
 
+use oorandom::Rand32;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
 const SHOT_SPEED: f32 = 100.0;
 
+// config/config.toml の自機の設定。書かなかった項目は既定値になる
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub struct PlayerConfig {
+    // 通常の弾に加えて、近くの敵を追いかける弾を左右に 1 発ずつ撃つか
+    #[serde(default)]
+    pub homing_shots: bool,
+}
+
+impl PlayerConfig {
+    pub fn load(path: &str) -> Result<PlayerConfig, Box<dyn Error>> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
 // TODO #2 無敵時間の実装
 #[derive(Debug)]
 pub struct ActorState {
     actor: Actor,
     shots: Vec<Actor>,
     shot_timeout: f32,
+    // 敵の弾幕パターン。複数持たせると並行して実行する
+    patterns: Vec<PatternRunner>,
+    // 移動経路と、その基準になる出現位置
+    path: Option<(Path, Vector2)>,
+    path_time: f32,
+    // 一度画面内に入ったか。入った後に画面外へ出た敵は消える
+    entered: bool,
+    // 画面内に入らないまま過ぎた時間 [s]。入らないまま長く残る敵も消す
+    outside_time: f32,
+    // Assets の画像かアニメーションの名前。None なら種類ごとの既定の画像
+    sprite: Option<String>,
+    // sprite のアニメーションを再生し始めてからの時間
+    anim_time: f32,
+    // 倒されたときに出る得点アイテムの数
+    drops: u32,
+    // 自機が誘導弾も撃つか
+    homing_shots: bool,
 }
 
 impl ActorState {
@@ -24,9 +64,23 @@ impl ActorState {
             actor: actor,
             shots: Vec::new(),
             shot_timeout: 0.0,
+            patterns: Vec::new(),
+            path: None,
+            path_time: 0.0,
+            entered: false,
+            outside_time: 0.0,
+            sprite: None,
+            anim_time: 0.0,
+            drops: 0,
+            homing_shots: false,
         }
     }
 
+    pub fn with_homing_shots(mut self, homing_shots: bool) -> Self {
+        self.homing_shots = homing_shots;
+        self
+    }
+
     pub fn get_actor(&self) -> Actor {
         self.actor
     }
@@ -42,12 +96,50 @@ impl ActorState {
     pub fn get_shot_timeout(&self) -> f32 {
         self.shot_timeout
     }
+    pub fn add_pattern(&mut self, pattern: PatternRunner) {
+        self.patterns.push(pattern);
+    }
+    // 違うスプライトに替えたときだけ、アニメーションを最初から再生する
+    pub fn set_sprite(&mut self, sprite: &str) {
+        if self.sprite.as_deref() != Some(sprite) {
+            self.sprite = Some(sprite.to_string());
+            self.anim_time = 0.0;
+        }
+    }
+    pub fn clear_sprite(&mut self) {
+        if self.sprite.is_some() {
+            self.sprite = None;
+            self.anim_time = 0.0;
+        }
+    }
+    pub fn get_drops(&self) -> u32 {
+        self.drops
+    }
+    pub fn set_drops(&mut self, drops: u32) {
+        self.drops = drops;
+    }
+    pub fn clear_patterns(&mut self) {
+        self.patterns.clear();
+    }
+    pub fn set_path(&mut self, path: Path) {
+        self.path = Some((path, self.actor.get_x_y()));
+        self.path_time = 0.0;
+    }
+    pub fn clear_path(&mut self) {
+        self.path = None;
+        self.actor.vel = Vector2(0.0, 0.0);
+    }
+
+    // 本体が倒されるか画面外へ消えても、撃った弾が残っている間は状態を残す
+    pub fn is_finished(&self) -> bool {
+        self.actor.get_life() <= 0 && self.shots.is_empty()
+    }
 
     fn _load() -> GameResult<ActorState> {
         unimplemented!();
     }
 
-    pub fn fire_shot(&mut self, _ctx: &Context) {
+    pub fn fire_shot(&mut self) {
         match self.actor.get_tag() {
             ActorType::Player => {
                 const PLAYER_SHOT_TIME: f32 = 0.5;
@@ -62,26 +154,104 @@ impl ActorState {
                 );
 
                 self.shots.extend(shot);
-                // ctx は音声の再生に用いる
-                // let _ = self.assets.shot_sound.play(ctx);
+                // 有効なら左右に 1 発ずつ、近くの敵を追いかける弾
+                if self.homing_shots {
+                    const HOMING_TURN_RATE: f32 = 4.0;
+                    const HOMING_DURATION: f32 = 1.5;
+                    for &angle in &[-0.5, 0.5] {
+                        self.shots.push(create_homing_bullet(
+                            player.get_x_y(),
+                            angle,
+                            SHOT_SPEED,
+                            HOMING_TURN_RATE,
+                            HOMING_DURATION,
+                            4.0,
+                        ));
+                    }
+                }
             }
-            ActorType::Enemy => {
-                const ENEMY_SHOT_TIME: f32 = 0.5;
-                self.shot_timeout = ENEMY_SHOT_TIME;
-                let enemy = &self.actor;
-                let shot = create_circle_bullets(
-                    enemy.get_x_y() + enemy.get_w_h() / 2.0,
-                    7,
-                    (0.0, 1.0),
-                    SHOT_SPEED / 8.0,
-                    0.01,
-                );
+            _ => (),
+        }
+    }
 
-                self.shots.extend(shot);
-                // ctx は音声の再生に用いる
-                // let _ = self.assets.shot_sound.play(ctx);
+    // パターンを dt だけ進めて弾を撃つ。target は狙う相手
+    pub fn run_pattern(
+        &mut self,
+        book: &PatternBook,
+        dt: f32,
+        rank: f32,
+        target: &Actor,
+        rng: &mut Rand32,
+    ) {
+        if self.actor.get_life() <= 0 {
+            return;
+        }
+        let context = PatternContext {
+            origin: self.actor.get_x_y(),
+            target: target.get_x_y(),
+            target_vel: target.vel,
+            rank: rank,
+        };
+        for pattern in &mut self.patterns {
+            pattern.update(book, dt, &context, rng, &mut self.shots);
+        }
+    }
+
+    // パターンを持つ弾を動かす。弾が撃った弾は最後にまとめて加える
+    pub fn run_shot_patterns(
+        &mut self,
+        book: &PatternBook,
+        dt: f32,
+        rank: f32,
+        target: &Actor,
+        rng: &mut Rand32,
+    ) {
+        let mut spawned = Vec::new();
+        for shot in &mut self.shots {
+            if let Some(mut pattern) = shot.get_pattern() {
+                let context = PatternContext {
+                    origin: shot.get_x_y(),
+                    target: target.get_x_y(),
+                    target_vel: target.vel,
+                    rank: rank,
+                };
+                pattern.update(book, dt, &context, rng, &mut spawned);
+                shot.vel = pattern.velocity();
+                if pattern.is_vanished() {
+                    shot.consume_all();
+                }
+                shot.set_pattern(pattern);
+            }
+        }
+        self.shots.extend(spawned);
+    }
+
+    // 残っている弾をすべて得点アイテムに変える。変えた数を返す
+    pub fn cancel_shots(&mut self, items: &mut Vec<Actor>) -> u32 {
+        let mut count = 0;
+        for shot in self.shots.drain(..) {
+            if shot.get_life() > 0 {
+                items.push(create_item(shot.get_x_y(), ItemOrigin::Cancel));
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // 誘導弾を targets のうち最も近いものへ曲げる
+    pub fn steer_shots(&mut self, targets: &[Vector2], dt: f32) {
+        for shot in &mut self.shots {
+            if !shot.is_homing() {
+                continue;
+            }
+            let x_y = shot.get_x_y();
+            let nearest = targets.iter().min_by(|a, b| {
+                let (da, db) = ((**a - x_y).norm_squared(), (**b - x_y).norm_squared());
+                da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            if let Some(&target) = nearest {
+                shot.steer_towards(target, dt);
             }
-            _ => (),
         }
     }
 
@@ -99,42 +269,89 @@ impl ActorState {
 
     pub fn clear_dead_stuff(&mut self, screen_w_h: Vector2) {
         self.shots
-            .retain(|s| s.inside_window(screen_w_h) && s.get_life() > 0);
+            .retain(|s| (s.inside_window(screen_w_h) || s.is_laser()) && s.get_life() > 0);
     }
 
     pub fn update(&mut self, seconds: f32, screen_w_h: Vector2) {
         for shot in self.get_mut_shots() {
             shot.update_actor_position(seconds);
+            shot.bounce_off_walls(screen_w_h);
             // wrap_actor_position(shot, self.screen_w_h);
             shot.dec_life(1);
         }
         self.dec_shot_timeout(seconds);
 
-        let actor = self.get_mut_actor();
+        if self.actor.get_life() <= 0 {
+            return;
+        }
+        self.anim_time += seconds;
+        if self.actor.get_tag() == ActorType::Player {
+            self.bank();
+        }
+        let actor = &mut self.actor;
         actor.dec_collision_timeout(seconds);
-        actor.update_actor_position(seconds);
-        actor.wrap_actor_position(screen_w_h);
+        match &self.path {
+            Some((path, origin)) => {
+                self.path_time += seconds;
+                actor.move_to(*origin + path.position(self.path_time), seconds);
+            }
+            None => actor.update_actor_position(seconds),
+        }
+        // 画面外から入ってくる敵を待つ時間の上限 [s]
+        const ENTER_TIMEOUT: f32 = 10.0;
+        match actor.get_tag() {
+            ActorType::Player => actor.wrap_actor_position(screen_w_h),
+            // ボスはフェーズの経路で画面の外から降りてくるので、画面外にいても消さない
+            ActorType::Boss => (),
+            _ if actor.inside_window(screen_w_h) => self.entered = true,
+            _ if self.entered => actor.consume_all(),
+            _ => {
+                self.outside_time += seconds;
+                if self.outside_time > ENTER_TIMEOUT {
+                    actor.consume_all();
+                }
+            }
+        }
+    }
+
+    // 自機は左右に動いている間、そちらへ傾くアニメーションにする
+    fn bank(&mut self) {
+        const BANK_THRESHOLD: f32 = 1.0;
+        let vx = self.actor.vel.0;
+        if vx < -BANK_THRESHOLD {
+            self.set_sprite("player_left");
+        } else if vx > BANK_THRESHOLD {
+            self.set_sprite("player_right");
+        } else {
+            self.clear_sprite();
+        }
     }
 
-    pub fn draw(&self, ctx: &mut Context, assets: &Assets, coords: (f32, f32)) -> GameResult {
+    // 機体と弾を描く予定に加える。実際に描くのは renderer を flush したとき
+    pub fn draw(&self, assets: &Assets, renderer: &mut SpriteRenderer, coords: (f32, f32)) {
         let actor = self.get_actor();
-        actor.draw(ctx, assets, coords)?;
-        actor.draw_collision(ctx, graphics::WHITE, coords)?;
-        // 残りライフの表示
-        draw_text(
-            ctx,
-            format!("{:#?}", actor.get_life()),
-            actor.get_x_y() + actor.get_w_h() * 2.0,
-            24.0,
-            assets.get_font(),
-            coords,
-        )?;
+        let tag = actor.get_tag();
+        if actor.get_life() > 0 {
+            match self
+                .sprite
+                .as_deref()
+                .and_then(|name| assets.get_sprite(name, self.anim_time))
+            {
+                Some(sprite) => renderer.add_sprite(Layer::of_body(tag), actor, sprite, coords),
+                None => renderer.add(Layer::of_body(tag), actor, assets, coords),
+            }
+            renderer.add_hitbox(actor, graphics::WHITE);
+            // 残りライフの表示
+            renderer.add_label(
+                actor.get_x_y() + actor.get_w_h() * 2.0,
+                format!("{:#?}", actor.get_life()),
+            );
+        }
 
         let color = graphics::Color::new(0.0, 1.0, 1.0, 1.0);
-        for shot in self.get_shots() {
-            shot.draw(ctx, assets, coords)?;
-            shot.draw_collision(ctx, color, coords)?;
+        for &shot in self.get_shots() {
+            renderer.add(Layer::of_shots(tag), shot, assets, coords);
+            renderer.add_hitbox(shot, color);
         }
-        Ok(())
     }
 }
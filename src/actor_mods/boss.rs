@@ -0,0 +1,277 @@
+use ggez::graphics;
+use ggez::{Context, GameResult};
+
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+use crate::actor_mods::actor::*;
+use crate::actor_mods::actor_state::ActorState;
+use crate::assets::Assets;
+use crate::difficulty::scale_hp;
+use crate::draw::draw_text;
+use crate::path_mods::path::{Path, PathBook};
+use crate::pattern_mods::pattern::PatternBook;
+use crate::pattern_mods::pattern_runner::PatternRunner;
+use crate::render_mods::layout::PLAYFIELD_W_H;
+use crate::vector2::Vector2;
+
+// ボスの 1 フェーズ。data/bosses.toml から読み込む
+#[derive(Clone, Debug, Deserialize)]
+struct PhaseDef {
+    hp: i32,
+    // 制限時間 [s]。過ぎると倒せなくても次のフェーズへ進む
+    time: f32,
+    pattern: String,
+    #[serde(default)]
+    path: Option<String>,
+    // スペルカード名。名前のあるフェーズは、被弾もボムもせずに倒すと bonus を得る
+    #[serde(default)]
+    card: Option<String>,
+    #[serde(default)]
+    bonus: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct BossDef {
+    name: String,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    max_collision_timeout: f32,
+    phase: Vec<PhaseDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct BossFile {
+    boss: Vec<BossDef>,
+}
+
+#[derive(Clone, Debug)]
+struct Phase {
+    hp: i32,
+    time: f32,
+    patterns: Vec<usize>,
+    path: Option<Path>,
+    card: Option<String>,
+    bonus: u32,
+}
+
+// ボスのフェーズの進行を管理する。ボス本体の ActorState は他の敵と同じく MainScene が持つ
+#[derive(Clone, Debug)]
+pub struct Boss {
+    name: String,
+    x_y: Vector2,
+    w_h: Vector2,
+    max_collision_timeout: f32,
+    phases: Vec<Phase>,
+    phase: usize,
+    phase_time: f32,
+    // 出現時の難易度による体力の倍率
+    hp_scale: f32,
+    // このフェーズの間に被弾またはボムを使ったか
+    failed: bool,
+    // このフェーズの体力を自機の弾で削りきったか。画面外で消えたときなどは立たない
+    broken: bool,
+    spawned: bool,
+    defeated: bool,
+}
+
+impl Boss {
+    // path から最初のボスを読み込み、パターンと経路の名前を解決する
+    pub fn load(
+        path: &str,
+        patterns: &PatternBook,
+        paths: &PathBook,
+    ) -> Result<Boss, Box<dyn Error>> {
+        let file: BossFile = toml::from_str(&fs::read_to_string(path)?)?;
+        let def = file.boss.into_iter().next().ok_or("no boss defined")?;
+        if def.phase.is_empty() {
+            return Err(format!("boss {} has no phase", def.name).into());
+        }
+        let mut phases = Vec::new();
+        for phase in def.phase {
+            let programs = patterns.find_looping(&phase.pattern)?;
+            let path = match &phase.path {
+                Some(name) => Some(
+                    paths
+                        .find(name)
+                        .ok_or_else(|| format!("unknown path: {}", name))?
+                        .clone(),
+                ),
+                None => None,
+            };
+            phases.push(Phase {
+                hp: phase.hp,
+                time: phase.time,
+                patterns: programs.to_vec(),
+                path: path,
+                card: phase.card,
+                bonus: phase.bonus,
+            });
+        }
+        Ok(Boss {
+            name: def.name,
+            x_y: Vector2(def.x, def.y),
+            w_h: Vector2(def.w, def.h),
+            max_collision_timeout: def.max_collision_timeout,
+            phases: phases,
+            phase: 0,
+            phase_time: 0.0,
+            hp_scale: 1.0,
+            failed: false,
+            broken: false,
+            spawned: false,
+            defeated: false,
+        })
+    }
+
+    pub fn is_spawned(&self) -> bool {
+        self.spawned
+    }
+
+    pub fn is_defeated(&self) -> bool {
+        self.defeated
+    }
+
+    // ボス本体を作り、最初のフェーズを始める。hp_scale は難易度による体力の倍率
+    pub fn spawn(&mut self, hp_scale: f32) -> ActorState {
+        self.hp_scale = hp_scale;
+        let actor = Actor::new(
+            ActorType::Boss,
+            self.x_y,
+            self.w_h,
+            0.0,
+            Vector2(0.0, 0.0),
+            0.0,
+            scale_hp(self.phases[0].hp, hp_scale),
+            self.max_collision_timeout,
+        );
+        let mut state = ActorState::new(actor);
+        self.spawned = true;
+        self.phase = 0;
+        self.enter_phase(&mut state);
+        state
+    }
+
+    fn enter_phase(&mut self, state: &mut ActorState) {
+        let phase = &self.phases[self.phase];
+        self.phase_time = 0.0;
+        self.failed = false;
+        self.broken = false;
+        state
+            .get_mut_actor()
+            .set_life(scale_hp(phase.hp, self.hp_scale));
+        state.clear_patterns();
+        for &program in &phase.patterns {
+            state.add_pattern(PatternRunner::new(program));
+        }
+        match &phase.path {
+            Some(path) => state.set_path(path.clone()),
+            // 経路のないフェーズでは、前のフェーズの速さのまま流されずにその場に止まる
+            None => {
+                state.clear_path();
+                state.get_mut_actor().vel = Vector2(0.0, 0.0);
+            }
+        }
+    }
+
+    // 被弾やボムで、今のスペルカードの取得に失敗する
+    pub fn fail_card(&mut self) {
+        self.failed = true;
+    }
+
+    // 自機の弾で体力を削りきった。スペルカードの取得はこのときだけ
+    pub fn break_phase(&mut self) {
+        self.broken = true;
+    }
+
+    // フェーズを dt だけ進める。フェーズが終わったら取得ボーナス (取れなければ 0) を返す
+    pub fn update(&mut self, state: &mut ActorState, dt: f32) -> Option<u32> {
+        if !self.spawned || self.defeated {
+            return None;
+        }
+        self.phase_time += dt;
+        let phase = &self.phases[self.phase];
+        let gone = self.broken || state.get_actor().get_life() <= 0;
+        if !gone && self.phase_time < phase.time {
+            return None;
+        }
+        let bonus = match phase.card {
+            Some(_) if self.broken && !self.failed => phase.bonus,
+            _ => 0,
+        };
+        if self.phase + 1 < self.phases.len() {
+            self.phase += 1;
+            self.enter_phase(state);
+        } else {
+            // 最後のフェーズで時間切れになったボスは退場する
+            self.defeated = true;
+            state.get_mut_actor().consume_all();
+        }
+        Some(bonus)
+    }
+
+    // プレイフィールドの上部に体力ゲージ、残りフェーズ数、残り時間、スペルカード名を表示する
+    pub fn draw_hud(
+        &self,
+        ctx: &mut Context,
+        state: &ActorState,
+        assets: &Assets,
+        coords: (f32, f32),
+    ) -> GameResult {
+        if !self.spawned || self.defeated {
+            return Ok(());
+        }
+        const MARGIN: f32 = 60.0;
+        const BAR_HEIGHT: f32 = 4.0;
+        let phase = &self.phases[self.phase];
+        let (w, h) = (PLAYFIELD_W_H.0, PLAYFIELD_W_H.1);
+        let max_hp = scale_hp(phase.hp, self.hp_scale);
+        let ratio = (state.get_actor().get_life() as f32 / max_hp as f32).max(0.0);
+        let bar_pos = Vector2(-w / 2.0 + MARGIN, h / 2.0 - 4.0).world_to_screen_coords(coords);
+        let bar = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(bar_pos.0, bar_pos.1, (w - MARGIN * 2.0) * ratio, BAR_HEIGHT),
+            graphics::Color::new(1.0, 0.3, 0.3, 1.0),
+        )?;
+        graphics::draw(ctx, &bar, graphics::DrawParam::default())?;
+
+        let top = h / 2.0 - 12.0;
+        let remaining = self.phases.len() - self.phase - 1;
+        draw_text(
+            ctx,
+            format!("{} x{}", self.name, remaining),
+            Vector2(-w / 2.0 + MARGIN / 2.0, top),
+            16.0,
+            assets.get_font(),
+            coords,
+        )?;
+        draw_text(
+            ctx,
+            format!("{:.1}", (phase.time - self.phase_time).max(0.0)),
+            Vector2(w / 2.0 - MARGIN / 2.0, top),
+            16.0,
+            assets.get_font(),
+            coords,
+        )?;
+        if let Some(card) = &phase.card {
+            let bonus = if self.failed {
+                "Bonus Failed".to_string()
+            } else {
+                format!("Bonus {}", phase.bonus)
+            };
+            draw_text(
+                ctx,
+                format!("{}\n{}", card, bonus),
+                Vector2(w / 2.0 - 120.0, top - 24.0),
+                12.0,
+                assets.get_font(),
+                coords,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,2 +1,3 @@
 pub mod actor;
 pub mod actor_state;
+pub mod boss;
@@ -1,5 +1,4 @@
 use ggez;
-use ggez::audio;
 use ggez::graphics;
 use ggez::{Context, GameResult};
 
@@ -7,23 +6,70 @@ use csv::Reader;
 use maplit::hashmap;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 
 use crate::actor_mods::actor::{Actor, ActorType};
+use crate::render_mods::animation::AnimationBook;
+use crate::render_mods::sprite_sheet::SheetIndex;
+use crate::vector2::Vector2;
+
+// スプライトシートの目次を置くディレクトリ
+const SHEETS_DIR: &str = "./resources/sheets";
+const ANIMATIONS_PATH: &str = "./data/animations.toml";
+
+// 1 枚の画像、またはスプライトシートから切り出した 1 フレーム
+#[derive(Clone, Debug)]
+pub struct Sprite {
+    image: graphics::Image,
+    // 画像全体を 1 とした切り出し範囲。DrawParam::src にそのまま渡す
+    src: graphics::Rect,
+    // 画像のパス。同じ画像のスプライトは 1 つの SpriteBatch にまとめられる
+    sheet: String,
+}
+
+impl Sprite {
+    pub fn new(image: graphics::Image, sheet: &str) -> Self {
+        Sprite {
+            image: image,
+            src: graphics::Rect::one(),
+            sheet: sheet.to_string(),
+        }
+    }
+
+    pub fn get_image(&self) -> &graphics::Image {
+        &self.image
+    }
+
+    pub fn get_src(&self) -> graphics::Rect {
+        self.src
+    }
+
+    // 切り出した範囲の大きさ [px]
+    pub fn get_w_h(&self) -> Vector2 {
+        Vector2(
+            self.image.width() as f32 * self.src.w,
+            self.image.height() as f32 * self.src.h,
+        )
+    }
+
+    pub fn get_sheet(&self) -> &str {
+        &self.sheet
+    }
+}
 
 #[derive(Debug)]
 pub struct Assets {
-    images: HashMap<&'static str, graphics::Image>,
-    sounds: HashMap<&'static str, audio::Source>,
+    sprites: HashMap<String, Sprite>,
+    // スプライトと同じ名前のアニメーションは、そのスプライトの代わりに使う
+    animations: AnimationBook,
     fonts: HashMap<&'static str, graphics::Font>,
-    // shot_sound: audio::Source,
-    // hit_sound: audio::Source,
 }
 
 impl Assets {
     pub fn new(_ctx: &mut Context) -> GameResult<Assets> {
         Ok(Assets {
-            images: hashmap!(),
-            sounds: hashmap!(),
+            sprites: hashmap!(),
+            animations: AnimationBook::new(),
             fonts: hashmap!(),
         })
     }
@@ -35,14 +81,22 @@ impl Assets {
 
         let normal_font = graphics::Font::new(ctx, "/LiberationMono-Regular.ttf")?;
 
-        // let shot_sound = audio::Source::new(ctx, "/pew.ogg")?;
-        // let hit_sound = audio::Source::new(ctx, "/boom.ogg")?;
-
-        let images = hashmap!(
-            "player" => player_image,
-            "enemy" => enemy_image,
-            "shot" => shot_image,
+        let mut sprites = hashmap!(
+            "player".to_string() => Sprite::new(player_image, "/player.png"),
+            "enemy".to_string() => Sprite::new(enemy_image, "/enemy.png"),
+            "shot".to_string() => Sprite::new(shot_image, "/shot.png"),
         );
+        // スプライトシートのフレームは、同じ名前の画像より優先する
+        load_sheets(ctx, &mut sprites)?;
+
+        let animations = AnimationBook::load(ANIMATIONS_PATH)?;
+        for (name, animation) in animations.iter() {
+            for frame in animation.get_frames() {
+                if !sprites.contains_key(frame) {
+                    return Err(format!("animation {}: unknown sprite: {}", name, frame).into());
+                }
+            }
+        }
 
         // csv から名前と画像を読み出して格納する
         // ＊record がダングリング(?)を引き起こしている模様
@@ -57,15 +111,13 @@ impl Assets {
         //     self.images.insert(path, image);
         // }
 
-        // let sounds = ... ;
-
         let fonts = hashmap!(
             "normal" => normal_font,
         );
 
         Ok(Assets {
-            images: images,
-            sounds: hashmap!(), // ← sounds
+            sprites: sprites,
+            animations: animations,
             fonts: fonts,
         })
     }
@@ -74,12 +126,82 @@ impl Assets {
         self.fonts["normal"]
     }
 
-    pub fn actor_image(&self, actor: Actor) -> &graphics::Image {
-        match actor.get_tag() {
-            ActorType::Player => &self.images["player"],
-            ActorType::Enemy => &self.images["enemy"],
-            ActorType::Bullet => &self.images["shot"],
-            ActorType::Other => &self.images["player"],
+    // name のアニメーションを time 秒再生したときのフレーム。アニメーションがなければ name のスプライト
+    pub fn get_sprite(&self, name: &str, time: f32) -> Option<&Sprite> {
+        match self.animations.find(name) {
+            Some(animation) => self.sprites.get(animation.frame_at(time)),
+            None => self.sprites.get(name),
+        }
+    }
+
+    pub fn has_sprite(&self, name: &str) -> bool {
+        self.sprites.contains_key(name) || self.animations.find(name).is_some()
+    }
+
+    // 一度だけ再生するアニメーションが終わったか。アニメーションでなければ終わらない
+    pub fn is_animation_finished(&self, name: &str, time: f32) -> bool {
+        self.animations
+            .find(name)
+            .map_or(false, |animation| animation.is_finished(time))
+    }
+
+    // 種類ごとの既定のスプライト。アニメーションは actor の年齢で進める
+    pub fn actor_sprite(&self, actor: Actor) -> &Sprite {
+        let name = actor_image_name(actor);
+        self.get_sprite(name, actor.get_age())
+            .unwrap_or_else(|| &self.sprites[name])
+    }
+}
+
+// SHEETS_DIR にある目次をすべて読み、フレームを sprites に加える
+fn load_sheets(
+    ctx: &mut Context,
+    sprites: &mut HashMap<String, Sprite>,
+) -> Result<(), Box<dyn Error>> {
+    let entries = match fs::read_dir(SHEETS_DIR) {
+        Ok(entries) => entries,
+        // シートを作っていなければ画像を 1 枚ずつ使う
+        Err(_) => return Ok(()),
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let index = SheetIndex::load(&path.to_string_lossy())?;
+        let image = graphics::Image::new(ctx, &index.image)?;
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        for frame in index.frame {
+            if frame.x + frame.w > width as u32 || frame.y + frame.h > height as u32 {
+                return Err(
+                    format!("{}: frame {} is out of the image", index.image, frame.name).into(),
+                );
+            }
+            let src = graphics::Rect::new(
+                frame.x as f32 / width,
+                frame.y as f32 / height,
+                frame.w as f32 / width,
+                frame.h as f32 / height,
+            );
+            let sprite = Sprite {
+                image: image.clone(),
+                src: src,
+                sheet: index.image.clone(),
+            };
+            sprites.insert(frame.name, sprite);
         }
     }
+    Ok(())
+}
+
+// actor の種類ごとの既定の画像名
+pub fn actor_image_name(actor: Actor) -> &'static str {
+    match actor.get_tag() {
+        ActorType::Player => "player",
+        ActorType::Enemy | ActorType::Boss => "enemy",
+        ActorType::Bullet | ActorType::Item => "shot",
+        ActorType::Effect => "explosion",
+        ActorType::Other => "player",
+    }
 }
@@ -0,0 +1,164 @@
+use ggez::audio::{self, SoundSource};
+use ggez::{Context, GameResult};
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_voices() -> usize {
+    4
+}
+
+// data/sounds.toml の効果音
+#[derive(Clone, Debug, Deserialize)]
+struct SoundDef {
+    // ゲームの出来事から引く名前
+    name: String,
+    // ggez の resources からのパス
+    path: String,
+    #[serde(default = "default_volume")]
+    volume: f32,
+    // 同時に鳴らせる数。超えると一番前に鳴らし始めたものを止めて鳴らし直す
+    #[serde(default = "default_voices")]
+    voices: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct SoundFile {
+    sound: Vec<SoundDef>,
+}
+
+// config/config.toml の音量の設定 [0, 1]。書かなかった項目は既定値になる
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct AudioConfig {
+    #[serde(default = "default_volume")]
+    pub master_volume: f32,
+    #[serde(default = "default_volume")]
+    pub sfx_volume: f32,
+    #[serde(default = "default_volume")]
+    pub bgm_volume: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            master_volume: 1.0,
+            sfx_volume: 1.0,
+            bgm_volume: 1.0,
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn load(path: &str) -> Result<AudioConfig, Box<dyn Error>> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Debug)]
+struct Sound {
+    // 同じ音声データを共有する再生口。順番に使い回す
+    voices: Vec<audio::Source>,
+    next: usize,
+    // このステップですでに鳴らしたか。大量のかすりなどで同じ音を重ねすぎないようにする
+    played: bool,
+}
+
+// 効果音と BGM を鳴らす。読み込みに失敗しても、音が出ないだけでゲームは続けられる
+#[derive(Debug, Default)]
+pub struct AudioManager {
+    sounds: HashMap<String, Sound>,
+    bgm: Option<audio::Source>,
+    config: AudioConfig,
+}
+
+impl AudioManager {
+    pub fn new() -> Self {
+        AudioManager::default()
+    }
+
+    pub fn load(
+        ctx: &mut Context,
+        path: &str,
+        config: AudioConfig,
+    ) -> Result<AudioManager, Box<dyn Error>> {
+        let file: SoundFile = toml::from_str(&fs::read_to_string(path)?)?;
+        let mut sounds = HashMap::new();
+        for def in file.sound {
+            if def.voices == 0 {
+                return Err(format!("sound {}: voices must be positive", def.name).into());
+            }
+            let data = audio::SoundData::new(ctx, &def.path)
+                .map_err(|err| format!("{}: {}", def.path, err))?;
+            let mut voices = Vec::new();
+            for _ in 0..def.voices {
+                let mut source = audio::Source::from_data(ctx, data.clone())?;
+                source.set_volume(def.volume * config.sfx_volume * config.master_volume);
+                voices.push(source);
+            }
+            let sound = Sound {
+                voices: voices,
+                next: 0,
+                played: false,
+            };
+            if sounds.insert(def.name.clone(), sound).is_some() {
+                return Err(format!("duplicate sound: {}", def.name).into());
+            }
+        }
+        Ok(AudioManager {
+            sounds: sounds,
+            bgm: None,
+            config: config,
+        })
+    }
+
+    // name の効果音を鳴らす。name がなければ何もしない
+    pub fn play(&mut self, name: &str) {
+        let sound = match self.sounds.get_mut(name) {
+            Some(sound) => sound,
+            None => return,
+        };
+        if sound.played {
+            return;
+        }
+        sound.played = true;
+        // 順番に使うので、空きがなければ一番前に鳴らし始めたものが鳴らし直される
+        let i = sound.next;
+        sound.next = (i + 1) % sound.voices.len();
+        let voice = &mut sound.voices[i];
+        voice.stop();
+        if let Err(err) = voice.play() {
+            println!("{}: {}", name, err);
+        }
+    }
+
+    // 次のステップでまた同じ効果音を鳴らせるようにする
+    pub fn update(&mut self) {
+        for sound in self.sounds.values_mut() {
+            sound.played = false;
+        }
+    }
+
+    // path の BGM を繰り返し再生する。鳴っていた BGM は止める
+    pub fn play_bgm(&mut self, ctx: &mut Context, path: &str) -> GameResult {
+        self.stop_bgm();
+        let mut bgm = audio::Source::new(ctx, path)?;
+        bgm.set_repeat(true);
+        bgm.set_volume(self.config.bgm_volume * self.config.master_volume);
+        bgm.play()?;
+        self.bgm = Some(bgm);
+        Ok(())
+    }
+
+    pub fn stop_bgm(&mut self) {
+        if let Some(bgm) = &mut self.bgm {
+            bgm.stop();
+        }
+        self.bgm = None;
+    }
+}
@@ -0,0 +1,93 @@
+extern crate my_first_ggez;
+
+use my_first_ggez::render_mods::sprite_sheet::{pack, Frame, SheetIndex};
+
+use image::{GenericImageView, RgbaImage};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+// ばらばらの PNG を 1 枚のスプライトシートにまとめ、目次の TOML と一緒に書き出す
+// フレーム名はファイル名 (拡張子なし) になる
+// cargo run --bin atlas_pack -- [入力ディレクトリ] [出力名]
+// 既定では ./resources/*.png を ./resources/sheets/sprites.png と sprites.toml にまとめる
+const DEFAULT_INPUT_DIR: &str = "./resources";
+const DEFAULT_OUTPUT: &str = "./resources/sheets/sprites";
+// 出力名の resources より後ろが ggez から見たパスになる
+const RESOURCES_DIR: &str = "./resources";
+const MAX_WIDTH: u32 = 1024;
+const PADDING: u32 = 2;
+
+fn run(input_dir: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    // 何か書き出す前に、ggez から読める場所か確かめる
+    let png_path = format!("{}.png", output);
+    let image_path = png_path
+        .strip_prefix(RESOURCES_DIR)
+        .ok_or_else(|| format!("output must be under {}", RESOURCES_DIR))?
+        .to_string();
+
+    let mut paths: Vec<_> = fs::read_dir(input_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "png"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("no png in {}", input_dir).into());
+    }
+
+    let mut images = Vec::new();
+    for path in &paths {
+        let image = image::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("bad file name: {}", path.display()))?;
+        images.push((name.to_string(), image));
+    }
+    let sizes: Vec<(u32, u32)> = images.iter().map(|(_, image)| image.dimensions()).collect();
+    let (width, height, positions) = pack(&sizes, MAX_WIDTH, PADDING)?;
+
+    let mut sheet = RgbaImage::new(width, height);
+    let mut frames = Vec::new();
+    for ((name, image), &(x, y)) in images.iter().zip(&positions) {
+        image::imageops::replace(&mut sheet, &image.to_rgba(), x, y);
+        let (w, h) = image.dimensions();
+        frames.push(Frame {
+            name: name.clone(),
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+        });
+    }
+
+    if let Some(dir) = Path::new(&png_path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    sheet.save(&png_path)?;
+    let index = SheetIndex {
+        image: image_path,
+        frame: frames,
+    };
+    fs::write(format!("{}.toml", output), toml::to_string(&index)?)?;
+    println!(
+        "packed {} images into {} ({}x{})",
+        images.len(),
+        png_path,
+        width,
+        height
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let input_dir = args.get(1).map_or(DEFAULT_INPUT_DIR, |s| s.as_str());
+    let output = args.get(2).map_or(DEFAULT_OUTPUT, |s| s.as_str());
+    if let Err(err) = run(input_dir, output) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,103 @@
+extern crate my_first_ggez;
+
+use my_first_ggez::actor_mods::actor::*;
+use my_first_ggez::collision_mods::spatial_hash::SpatialHash;
+use my_first_ggez::vector2::{random_vec, Vector2};
+
+use oorandom::Rand32;
+use std::time::{Duration, Instant};
+
+// 総当たりとグリッドによる当たり判定の所要時間を比べる
+// cargo run --release --bin collision_bench
+const SCREEN_W_H: Vector2 = Vector2(800.0, 600.0);
+const TARGET_NUM: usize = 32;
+const ITERATIONS: u32 = 100;
+
+fn random_pos(rng: &mut Rand32) -> Vector2 {
+    Vector2(
+        (rng.rand_float() - 0.5) * SCREEN_W_H.0,
+        (rng.rand_float() - 0.5) * SCREEN_W_H.1,
+    )
+}
+
+fn brute_force(targets: &[Actor], shots: &[Actor]) -> usize {
+    let mut hits = 0;
+    for target in targets {
+        for shot in shots {
+            if target.overlaps(shot) {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+fn grid(targets: &[Actor], shots: &[Actor], grid: &mut SpatialHash) -> usize {
+    let mut hits = 0;
+    let mut candidates = Vec::new();
+    grid.clear();
+    for (j, shot) in shots.iter().enumerate() {
+        grid.insert((0, j as u32), shot.get_x_y(), shot.bounding_radius());
+    }
+    for target in targets {
+        candidates.clear();
+        grid.query(target.get_x_y(), target.bounding_radius(), &mut candidates);
+        for &(_, j) in candidates.iter() {
+            if target.overlaps(&shots[j as usize]) {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+fn measure<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut hits = 0;
+    for _ in 0..ITERATIONS {
+        hits = f();
+    }
+    (start.elapsed() / ITERATIONS, hits)
+}
+
+pub fn main() {
+    let mut rng = Rand32::new(0);
+    let mut spatial_hash = SpatialHash::new(SCREEN_W_H, 32.0);
+    let targets: Vec<Actor> = (0..TARGET_NUM)
+        .map(|_| {
+            let x_y = random_pos(&mut rng);
+            let zero = Vector2(0.0, 0.0);
+            Actor::new(
+                ActorType::Enemy,
+                x_y,
+                Vector2(16.0, 16.0),
+                0.0,
+                zero,
+                0.0,
+                1,
+                0.0,
+            )
+        })
+        .collect();
+
+    for &num in &[10_000, 50_000] {
+        let shots: Vec<Actor> = (0..num)
+            .map(|_| {
+                let x_y = random_pos(&mut rng);
+                create_bullet(x_y, Vector2(4.0, 4.0), 0.0, random_vec(&mut rng, 50.0), 0.0)
+            })
+            .collect();
+
+        let (brute_time, brute_hits) = measure(|| brute_force(&targets, &shots));
+        let (grid_time, grid_hits) = measure(|| grid(&targets, &shots, &mut spatial_hash));
+        println!(
+            "{} bullets: brute force {:?} (hits {}), grid {:?} (hits {}), {:.1}x",
+            num,
+            brute_time,
+            brute_hits,
+            grid_time,
+            grid_hits,
+            brute_time.as_secs_f64() / grid_time.as_secs_f64(),
+        );
+    }
+}
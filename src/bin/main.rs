@@ -19,6 +19,6 @@ pub fn main() -> ggez::GameResult {
     let (ctx, event_loop) = &mut cb.build()?;
     let hidpi_factor = event_loop.get_primary_monitor().get_hidpi_factor() as f32;
     let state = &mut MainScene::new(ctx, hidpi_factor)?;
-    state.load_data()?;
+    state.load_data(ctx)?;
     event::run(ctx, event_loop, state)
 }
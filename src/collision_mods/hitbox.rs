@@ -0,0 +1,385 @@
+use serde::Deserialize;
+
+use crate::vector2::{vec_from_angle, Vector2};
+
+// 当たり判定の形。回転は持ち主の向き (facing) に従い、
+// 局所座標の y 軸が facing の方向、x 軸がその右手方向になる
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Hitbox {
+    Circle { radius: f32 },
+    Rect { half_w: f32, half_h: f32 },
+    // facing 方向に伸びる線分に太さを持たせた形。長い弾やレーザーに用いる
+    Capsule { half_len: f32, radius: f32 },
+}
+
+impl Hitbox {
+    // 画像の大きさから外接円を作る
+    pub fn from_w_h(w_h: Vector2) -> Self {
+        Hitbox::Circle {
+            radius: w_h.norm() / 2.0,
+        }
+    }
+
+    // 回転に関係なく形全体を含む円の半径
+    pub fn bounding_radius(self) -> f32 {
+        match self {
+            Hitbox::Circle { radius } => radius,
+            Hitbox::Rect { half_w, half_h } => Vector2(half_w, half_h).norm(),
+            Hitbox::Capsule { half_len, radius } => half_len + radius,
+        }
+    }
+
+    pub fn intersects(
+        self,
+        x_y: Vector2,
+        angle: f32,
+        other: Hitbox,
+        other_x_y: Vector2,
+        other_angle: f32,
+    ) -> bool {
+        use Hitbox::*;
+        match (self, other) {
+            (Circle { radius: r1 }, Circle { radius: r2 }) => {
+                (other_x_y - x_y).norm_squared() < (r1 + r2) * (r1 + r2)
+            }
+            (Circle { radius }, Rect { half_w, half_h }) => {
+                let p = to_local(x_y, other_x_y, other_angle);
+                point_box_distance_squared(p, Vector2(half_w, half_h)) < radius * radius
+            }
+            (
+                Circle { radius: r1 },
+                Capsule {
+                    half_len,
+                    radius: r2,
+                },
+            ) => {
+                let (a, b) = segment(other_x_y, other_angle, half_len);
+                (closest_on_segment(x_y, a, b) - x_y).norm_squared() < (r1 + r2) * (r1 + r2)
+            }
+            (
+                Rect {
+                    half_w: w1,
+                    half_h: h1,
+                },
+                Rect {
+                    half_w: w2,
+                    half_h: h2,
+                },
+            ) => boxes_overlap(
+                (x_y, angle, Vector2(w1, h1)),
+                (other_x_y, other_angle, Vector2(w2, h2)),
+            ),
+            (Rect { half_w, half_h }, Capsule { half_len, radius }) => {
+                let (a, b) = segment(other_x_y, other_angle, half_len);
+                let a = to_local(a, x_y, angle);
+                let b = to_local(b, x_y, angle);
+                segment_box_distance_squared(a, b, Vector2(half_w, half_h)) < radius * radius
+            }
+            (
+                Capsule {
+                    half_len: l1,
+                    radius: r1,
+                },
+                Capsule {
+                    half_len: l2,
+                    radius: r2,
+                },
+            ) => {
+                let (a1, b1) = segment(x_y, angle, l1);
+                let (a2, b2) = segment(other_x_y, other_angle, l2);
+                segment_distance_squared(a1, b1, a2, b2) < (r1 + r2) * (r1 + r2)
+            }
+            (Rect { .. }, Circle { .. })
+            | (Capsule { .. }, Circle { .. })
+            | (Capsule { .. }, Rect { .. }) => {
+                other.intersects(other_x_y, other_angle, self, x_y, angle)
+            }
+        }
+    }
+
+    // デバッグ表示用の輪郭 (ワールド座標)
+    pub fn outline(self, x_y: Vector2, angle: f32) -> Vec<Vector2> {
+        const ARC_SEGMENTS: usize = 8;
+        let (right, forward) = axes(angle);
+        let arc = |center: Vector2, radius: f32, start: f32| {
+            (0..=ARC_SEGMENTS).map(move |i| {
+                let t = start + std::f32::consts::PI * i as f32 / ARC_SEGMENTS as f32;
+                center + vec_from_angle(t) * radius
+            })
+        };
+        match self {
+            Hitbox::Circle { radius } => (0..ARC_SEGMENTS * 2)
+                .map(|i| {
+                    let t = std::f32::consts::PI * i as f32 / ARC_SEGMENTS as f32;
+                    x_y + vec_from_angle(t) * radius
+                })
+                .collect(),
+            Hitbox::Rect { half_w, half_h } => vec![
+                x_y + right * half_w + forward * half_h,
+                x_y + right * half_w - forward * half_h,
+                x_y - right * half_w - forward * half_h,
+                x_y - right * half_w + forward * half_h,
+            ],
+            Hitbox::Capsule { half_len, radius } => {
+                let (tail, head) = segment(x_y, angle, half_len);
+                let start = angle - std::f32::consts::FRAC_PI_2;
+                arc(head, radius, start)
+                    .chain(arc(tail, radius, start + std::f32::consts::PI))
+                    .collect()
+            }
+        }
+    }
+}
+
+// (右手方向, 正面方向)
+fn axes(angle: f32) -> (Vector2, Vector2) {
+    (
+        vec_from_angle(angle + std::f32::consts::FRAC_PI_2),
+        vec_from_angle(angle),
+    )
+}
+
+fn segment(x_y: Vector2, angle: f32, half_len: f32) -> (Vector2, Vector2) {
+    let forward = vec_from_angle(angle) * half_len;
+    (x_y - forward, x_y + forward)
+}
+
+// p を center, angle の局所座標に直す
+fn to_local(p: Vector2, center: Vector2, angle: f32) -> Vector2 {
+    let (right, forward) = axes(angle);
+    let d = p - center;
+    Vector2(d.dot(right), d.dot(forward))
+}
+
+pub fn closest_on_segment(p: Vector2, a: Vector2, b: Vector2) -> Vector2 {
+    let ab = b - a;
+    let len_squared = ab.norm_squared();
+    if len_squared <= std::f32::EPSILON {
+        return a;
+    }
+    let t = ((p - a).dot(ab) / len_squared).max(0.0).min(1.0);
+    a + ab * t
+}
+
+fn segments_cross(a1: Vector2, b1: Vector2, a2: Vector2, b2: Vector2) -> bool {
+    let cross =
+        |o: Vector2, p: Vector2, q: Vector2| (p.0 - o.0) * (q.1 - o.1) - (p.1 - o.1) * (q.0 - o.0);
+    let d1 = cross(a2, b2, a1);
+    let d2 = cross(a2, b2, b1);
+    let d3 = cross(a1, b1, a2);
+    let d4 = cross(a1, b1, b2);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+// 交わらない 2 線分の最短距離は、必ずどちらかの端点で取られる
+pub fn segment_distance_squared(a1: Vector2, b1: Vector2, a2: Vector2, b2: Vector2) -> f32 {
+    if segments_cross(a1, b1, a2, b2) {
+        return 0.0;
+    }
+    let d = |p: Vector2, a: Vector2, b: Vector2| (closest_on_segment(p, a, b) - p).norm_squared();
+    d(a1, a2, b2)
+        .min(d(b1, a2, b2))
+        .min(d(a2, a1, b1))
+        .min(d(b2, a1, b1))
+}
+
+// 原点中心、半分の大きさ half の軸平行な箱と点 p の距離の二乗
+fn point_box_distance_squared(p: Vector2, half: Vector2) -> f32 {
+    let dx = (p.0.abs() - half.0).max(0.0);
+    let dy = (p.1.abs() - half.1).max(0.0);
+    dx * dx + dy * dy
+}
+
+// スラブ法による線分と箱の交差判定
+fn segment_hits_box(a: Vector2, b: Vector2, half: Vector2) -> bool {
+    let d = b - a;
+    let (mut t_min, mut t_max) = (0.0_f32, 1.0_f32);
+    for &(start, dir, extent) in &[(a.0, d.0, half.0), (a.1, d.1, half.1)] {
+        if dir.abs() <= std::f32::EPSILON {
+            if start.abs() > extent {
+                return false;
+            }
+        } else {
+            let t1 = (-extent - start) / dir;
+            let t2 = (extent - start) / dir;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+            if t_min > t_max {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn segment_box_distance_squared(a: Vector2, b: Vector2, half: Vector2) -> f32 {
+    if segment_hits_box(a, b, half) {
+        return 0.0;
+    }
+    let corners = [
+        Vector2(half.0, half.1),
+        Vector2(half.0, -half.1),
+        Vector2(-half.0, half.1),
+        Vector2(-half.0, -half.1),
+    ];
+    corners
+        .iter()
+        .map(|&c| (closest_on_segment(c, a, b) - c).norm_squared())
+        .fold(
+            point_box_distance_squared(a, half).min(point_box_distance_squared(b, half)),
+            f32::min,
+        )
+}
+
+// 分離軸判定。軸は両方の箱の辺の向き
+fn boxes_overlap(box1: (Vector2, f32, Vector2), box2: (Vector2, f32, Vector2)) -> bool {
+    let (right1, forward1) = axes(box1.1);
+    let (right2, forward2) = axes(box2.1);
+    let d = box2.0 - box1.0;
+    let extent = |axis: Vector2, right: Vector2, forward: Vector2, half: Vector2| {
+        half.0 * right.dot(axis).abs() + half.1 * forward.dot(axis).abs()
+    };
+    [right1, forward1, right2, forward2].iter().all(|&axis| {
+        d.dot(axis).abs()
+            < extent(axis, right1, forward1, box1.2) + extent(axis, right2, forward2, box2.2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    const ORIGIN: Vector2 = Vector2(0.0, 0.0);
+
+    fn hits(
+        a: Hitbox,
+        a_x_y: Vector2,
+        a_angle: f32,
+        b: Hitbox,
+        b_x_y: Vector2,
+        b_angle: f32,
+    ) -> bool {
+        let ab = a.intersects(a_x_y, a_angle, b, b_x_y, b_angle);
+        let ba = b.intersects(b_x_y, b_angle, a, a_x_y, a_angle);
+        assert_eq!(ab, ba, "intersects must not depend on the order");
+        ab
+    }
+
+    #[test]
+    fn circles_overlap_only_within_the_sum_of_radii() {
+        let circle = Hitbox::Circle { radius: 2.0 };
+        assert!(hits(circle, ORIGIN, 0.0, circle, Vector2(3.9, 0.0), 0.0));
+        assert!(!hits(circle, ORIGIN, 0.0, circle, Vector2(4.1, 0.0), 0.0));
+    }
+
+    #[test]
+    fn rect_follows_the_facing_angle() {
+        // 向き 0 では横に長く、90 度回すと縦に長くなる
+        let rect = Hitbox::Rect {
+            half_w: 10.0,
+            half_h: 2.0,
+        };
+        let dot = Hitbox::Circle { radius: 1.0 };
+        assert!(hits(dot, Vector2(8.0, 0.0), 0.0, rect, ORIGIN, 0.0));
+        assert!(!hits(dot, Vector2(0.0, 8.0), 0.0, rect, ORIGIN, 0.0));
+        assert!(!hits(dot, Vector2(8.0, 0.0), 0.0, rect, ORIGIN, FRAC_PI_2));
+        assert!(hits(dot, Vector2(0.0, 8.0), 0.0, rect, ORIGIN, FRAC_PI_2));
+    }
+
+    #[test]
+    fn rotated_rects_use_separating_axes() {
+        // 外接する軸平行な箱は重なるが、45 度傾いた辺の方向で離れている
+        let square = Hitbox::Rect {
+            half_w: 1.0,
+            half_h: 1.0,
+        };
+        assert!(!hits(
+            square,
+            ORIGIN,
+            FRAC_PI_4,
+            square,
+            Vector2(1.6, 1.6),
+            FRAC_PI_4
+        ));
+        assert!(hits(
+            square,
+            ORIGIN,
+            FRAC_PI_4,
+            square,
+            Vector2(1.3, 1.3),
+            FRAC_PI_4
+        ));
+        // 角が辺に刺さる
+        assert!(hits(
+            square,
+            ORIGIN,
+            0.0,
+            square,
+            Vector2(2.3, 0.0),
+            FRAC_PI_4
+        ));
+        assert!(!hits(
+            square,
+            ORIGIN,
+            0.0,
+            square,
+            Vector2(2.5, 0.0),
+            FRAC_PI_4
+        ));
+    }
+
+    #[test]
+    fn capsule_is_a_thick_segment() {
+        let capsule = Hitbox::Capsule {
+            half_len: 10.0,
+            radius: 1.0,
+        };
+        let dot = Hitbox::Circle { radius: 1.0 };
+        assert!(hits(dot, Vector2(1.5, 9.0), 0.0, capsule, ORIGIN, 0.0));
+        assert!(!hits(dot, Vector2(1.5, 12.0), 0.0, capsule, ORIGIN, 0.0));
+        // 十字に交わるカプセル
+        assert!(hits(capsule, ORIGIN, 0.0, capsule, ORIGIN, FRAC_PI_2));
+        // 平行に並んだカプセル
+        assert!(!hits(capsule, ORIGIN, 0.0, capsule, Vector2(2.5, 0.0), 0.0));
+        assert!(hits(capsule, ORIGIN, 0.0, capsule, Vector2(1.5, 0.0), 0.0));
+    }
+
+    #[test]
+    fn capsule_against_rect() {
+        let capsule = Hitbox::Capsule {
+            half_len: 10.0,
+            radius: 1.0,
+        };
+        let rect = Hitbox::Rect {
+            half_w: 2.0,
+            half_h: 2.0,
+        };
+        // 線分が箱を貫く
+        assert!(hits(capsule, Vector2(0.0, 5.0), 0.0, rect, ORIGIN, 0.0));
+        assert!(hits(capsule, Vector2(2.5, 0.0), 0.0, rect, ORIGIN, 0.0));
+        assert!(!hits(capsule, Vector2(3.5, 0.0), 0.0, rect, ORIGIN, 0.0));
+    }
+
+    #[test]
+    fn outline_stays_inside_the_bounding_radius() {
+        let shapes = [
+            Hitbox::Circle { radius: 3.0 },
+            Hitbox::Rect {
+                half_w: 4.0,
+                half_h: 1.0,
+            },
+            Hitbox::Capsule {
+                half_len: 5.0,
+                radius: 2.0,
+            },
+        ];
+        let center = Vector2(10.0, -4.0);
+        for &shape in &shapes {
+            for p in shape.outline(center, 0.7) {
+                assert!((p - center).norm() <= shape.bounding_radius() + 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod hitbox;
+pub mod spatial_hash;
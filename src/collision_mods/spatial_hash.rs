@@ -0,0 +1,77 @@
+use crate::vector2::Vector2;
+
+// 弾同士の当たり判定を絞り込むための一様グリッド (broad phase)
+// ワールド座標は画面中央が原点なので、グリッドも原点を中心に張る
+#[derive(Debug)]
+pub struct SpatialHash {
+    area_w_h: Vector2,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    // 各セルに入っている要素のキー (ActorState の番号, 弾の番号)
+    cells: Vec<Vec<(u32, u32)>>,
+    // 登録された要素の最大半径。検索範囲をこの分だけ広げる
+    max_radius: f32,
+}
+
+impl SpatialHash {
+    pub fn new(area_w_h: Vector2, cell_size: f32) -> Self {
+        let mut grid = SpatialHash {
+            area_w_h: Vector2(0.0, 0.0),
+            cell_size: cell_size,
+            cols: 0,
+            rows: 0,
+            cells: Vec::new(),
+            max_radius: 0.0,
+        };
+        grid.resize(area_w_h);
+        grid
+    }
+
+    pub fn resize(&mut self, area_w_h: Vector2) {
+        if self.area_w_h == area_w_h {
+            return;
+        }
+        self.area_w_h = area_w_h;
+        self.cols = ((area_w_h.0 / self.cell_size).ceil() as usize).max(1);
+        self.rows = ((area_w_h.1 / self.cell_size).ceil() as usize).max(1);
+        self.cells = vec![Vec::new(); self.cols * self.rows];
+        self.max_radius = 0.0;
+    }
+
+    // セルの確保済み領域は使い回す
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            cell.clear();
+        }
+        self.max_radius = 0.0;
+    }
+
+    // 画面外の座標は端のセルに丸める
+    fn cell_coords(&self, x_y: Vector2) -> (usize, usize) {
+        let x = (x_y.0 + self.area_w_h.0 / 2.0) / self.cell_size;
+        let y = (x_y.1 + self.area_w_h.1 / 2.0) / self.cell_size;
+        let col = (x.max(0.0) as usize).min(self.cols - 1);
+        let row = (y.max(0.0) as usize).min(self.rows - 1);
+        (col, row)
+    }
+
+    // 要素は中心のセルにだけ登録し、大きさは検索側で吸収する
+    pub fn insert(&mut self, key: (u32, u32), x_y: Vector2, radius: f32) {
+        let (col, row) = self.cell_coords(x_y);
+        self.cells[row * self.cols + col].push(key);
+        self.max_radius = self.max_radius.max(radius);
+    }
+
+    // x_y を中心とする半径 radius の円と重なりうる要素のキーを out に追加する
+    pub fn query(&self, x_y: Vector2, radius: f32, out: &mut Vec<(u32, u32)>) {
+        let reach = Vector2(radius, radius) + Vector2(self.max_radius, self.max_radius);
+        let (min_col, min_row) = self.cell_coords(x_y - reach);
+        let (max_col, max_row) = self.cell_coords(x_y + reach);
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                out.extend_from_slice(&self.cells[row * self.cols + col]);
+            }
+        }
+    }
+}
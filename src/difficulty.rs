@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+// 動的ランクが基本値から離れられる幅
+const MAX_DYNAMIC_OFFSET: f32 = 0.25;
+// 生き延びた 1 秒あたりに上がるランク
+const RANK_PER_SECOND: f32 = 0.002;
+// 得点 1 点あたりに上がるランク
+const RANK_PER_POINT: f32 = 0.000_005;
+// 被弾したときに下がるランク
+const RANK_DROP_ON_DEATH: f32 = 0.15;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Lunatic,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl Difficulty {
+    // ランクの基本値。Normal の 0.5 でパターンを書いたとおりの弾数・速さ・体力になる
+    pub fn base_rank(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.5,
+            Difficulty::Hard => 0.7,
+            Difficulty::Lunatic => 0.9,
+        }
+    }
+}
+
+// config/config.toml の難易度の設定。書かなかった項目は既定値になる
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+struct RankConfig {
+    #[serde(default)]
+    difficulty: Difficulty,
+    // 得点と生存時間で上がり、被弾で下がるランクを使うか
+    #[serde(default)]
+    dynamic_rank: bool,
+}
+
+// パターンと敵の体力に渡す難易度 [0, 1]
+// 入力と経過フレームだけで決まるので、同じ操作をすれば同じ値になる
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rank {
+    difficulty: Difficulty,
+    dynamic: bool,
+    // 動的ランクによる基本値からのずれ
+    offset: f32,
+    // 前回ランクに反映した得点
+    last_score: u32,
+}
+
+impl Rank {
+    pub fn new(difficulty: Difficulty, dynamic: bool) -> Self {
+        Rank {
+            difficulty: difficulty,
+            dynamic: dynamic,
+            offset: 0.0,
+            last_score: 0,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Rank, Box<dyn Error>> {
+        let config: RankConfig = toml::from_str(&fs::read_to_string(path)?)?;
+        Ok(Rank::new(config.difficulty, config.dynamic_rank))
+    }
+
+    pub fn get_difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic
+    }
+
+    pub fn value(&self) -> f32 {
+        (self.difficulty.base_rank() + self.offset)
+            .max(0.0)
+            .min(1.0)
+    }
+
+    // 敵の体力の倍率。ランク 0.5 で 1 倍、0 で 0.75 倍、1 で 1.25 倍
+    pub fn hp_scale(&self) -> f32 {
+        0.75 + self.value() * 0.5
+    }
+
+    // 生き延びた時間と増えた得点の分だけランクを上げる
+    pub fn update(&mut self, dt: f32, score: u32) {
+        let gained = score.saturating_sub(self.last_score);
+        self.last_score = score;
+        if self.dynamic {
+            self.add(dt * RANK_PER_SECOND + gained as f32 * RANK_PER_POINT);
+        }
+    }
+
+    pub fn on_death(&mut self) {
+        if self.dynamic {
+            self.add(-RANK_DROP_ON_DEATH);
+        }
+    }
+
+    fn add(&mut self, delta: f32) {
+        self.offset = (self.offset + delta)
+            .max(-MAX_DYNAMIC_OFFSET)
+            .min(MAX_DYNAMIC_OFFSET);
+    }
+}
+
+impl Default for Rank {
+    fn default() -> Self {
+        Rank::new(Difficulty::default(), false)
+    }
+}
+
+// 弾数と速さの倍率。ランク 0.5 で 1 倍になる
+pub fn rank_count(count: u32, rank: f32) -> u32 {
+    ((count as f32 * (0.5 + rank)).round() as u32).max(1)
+}
+
+pub fn rank_speed(speed: f32, rank: f32) -> f32 {
+    speed * (0.8 + rank * 0.4)
+}
+
+// 倍率をかけた敵の体力。倍率が小さくても 1 は残す
+pub fn scale_hp(hp: i32, scale: f32) -> i32 {
+    ((hp as f32 * scale).round() as i32).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_count_scales_around_normal() {
+        // ランク 0.5 で書いたとおり、0 で半分、1 で 1.5 倍
+        assert_eq!(rank_count(8, 0.5), 8);
+        assert_eq!(rank_count(8, 0.0), 4);
+        assert_eq!(rank_count(8, 1.0), 12);
+        // 四捨五入し、少なくとも 1 発は撃つ
+        assert_eq!(rank_count(3, 0.2), 2);
+        assert_eq!(rank_count(1, 0.0), 1);
+        assert_eq!(rank_count(0, 1.0), 1);
+    }
+
+    #[test]
+    fn scale_hp_rounds_and_keeps_one() {
+        assert_eq!(scale_hp(10, 1.0), 10);
+        assert_eq!(scale_hp(10, 0.75), 8);
+        assert_eq!(scale_hp(10, 1.25), 13);
+        assert_eq!(scale_hp(1, 0.75), 1);
+        assert_eq!(scale_hp(0, 1.25), 1);
+    }
+
+    #[test]
+    fn hp_scale_follows_difficulty() {
+        assert_eq!(Rank::new(Difficulty::Normal, false).hp_scale(), 1.0);
+        let easy = Rank::new(Difficulty::Easy, false).hp_scale();
+        let lunatic = Rank::new(Difficulty::Lunatic, false).hp_scale();
+        assert!(easy < 1.0 && lunatic > 1.0);
+    }
+
+    #[test]
+    fn dynamic_rank_moves_within_its_range() {
+        let mut fixed = Rank::new(Difficulty::Normal, false);
+        fixed.update(100.0, 1_000_000);
+        assert_eq!(fixed.value(), 0.5);
+
+        let mut dynamic = Rank::new(Difficulty::Normal, true);
+        dynamic.update(100.0, 1_000_000);
+        assert!((dynamic.value() - (0.5 + MAX_DYNAMIC_OFFSET)).abs() < 1e-6);
+        dynamic.on_death();
+        assert!(dynamic.value() < 0.5 + MAX_DYNAMIC_OFFSET);
+    }
+}
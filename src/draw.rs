@@ -23,3 +23,18 @@ pub fn draw_text(
     graphics::draw(ctx, &text, drawparams)?;
     Ok(())
 }
+
+// 画面座標の x_y を左上として描く。HUD などワールドに属さない文字に用いる
+pub fn draw_screen_text(
+    ctx: &mut Context,
+    text: String,
+    x_y: Vector2,
+    size: f32,
+    font: graphics::Font,
+) -> GameResult {
+    let text = graphics::Text::new((text, font, size));
+    let drawparams = graphics::DrawParam::new().dest(na::Point2::new(x_y.0, x_y.1));
+
+    graphics::draw(ctx, &text, drawparams)?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use crate::actor_mods::actor::ActorType;
+use crate::vector2::Vector2;
+
+// 1 フレームの間に発生した出来事。スコアやエフェクト、効果音はこれを見て反応する
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    // 自機が弾を撃った
+    Shot {
+        x_y: Vector2,
+    },
+    // 弾が target に命中した。x_y は命中した位置
+    Hit {
+        target: ActorType,
+        x_y: Vector2,
+        killed: bool,
+    },
+    // 敵弾を count 個打ち消して得点アイテムに変えた
+    Cancel {
+        count: u32,
+    },
+    // ボスのフェーズが終わった。bonus はスペルカードの取得ボーナス (取れなければ 0)
+    PhaseCleared {
+        bonus: u32,
+    },
+    // 自機が得点アイテムを拾った。cancelled は打ち消した弾から出たアイテムか
+    ItemCollected {
+        x_y: Vector2,
+        cancelled: bool,
+    },
+    // 敵弾が自機をかすめた
+    Graze {
+        x_y: Vector2,
+    },
+    // 自機がボムを使った。x_y は自機の位置
+    Bomb {
+        x_y: Vector2,
+    },
+}
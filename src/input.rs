@@ -4,6 +4,8 @@ pub struct InputState {
     xaxis: f32,
     yaxis: f32,
     fire: bool,
+    // 押された瞬間だけ立てて、使ったら下ろす
+    bomb: bool,
 }
 
 impl InputState {
@@ -16,6 +18,9 @@ impl InputState {
     pub fn get_fire(&self) -> bool {
         self.fire
     }
+    pub fn take_bomb(&mut self) -> bool {
+        std::mem::replace(&mut self.bomb, false)
+    }
 
     pub fn move_actor(&mut self, keycode: KeyCode) {
         match keycode {
@@ -54,6 +59,14 @@ impl InputState {
             _ => (),
         }
     }
+    pub fn press_bomb(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::X => {
+                self.bomb = true;
+            }
+            _ => (),
+        }
+    }
     pub fn stop_shot(&mut self, keycode: KeyCode) {
         match keycode {
             KeyCode::Z => {
@@ -70,6 +83,7 @@ impl Default for InputState {
             xaxis: 0.0,
             yaxis: 0.0,
             fire: false,
+            bomb: false,
         }
     }
 }
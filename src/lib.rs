@@ -1,7 +1,16 @@
 pub mod actor_mods;
 pub mod assets;
+pub mod audio_mods;
+pub mod collision_mods;
+pub mod difficulty;
 pub mod draw;
+pub mod game_event;
 pub mod imgui_wrapper;
 pub mod input;
+pub mod path_mods;
+pub mod pattern_mods;
+pub mod render_mods;
+pub mod run_record;
 pub mod scene_mods;
+pub mod stage_mods;
 pub mod vector2;
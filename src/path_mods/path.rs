@@ -0,0 +1,443 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use crate::vector2::Vector2;
+
+// 区間の中での進み方
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+impl Easing {
+    // t ∈ [0, 1] を進み具合 [0, 1] に変換する
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+// 終点に着いた後の動き
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathEnd {
+    // 最後の区間を等速で進んだときの速度のまま直進する
+    Drift,
+    // 終点に止まる
+    Hold,
+}
+
+impl Default for PathEnd {
+    fn default() -> Self {
+        Self::Drift
+    }
+}
+
+// 敵の移動経路。data/paths.toml から読み込む
+// 座標は出現位置からの相対位置 [px]、時間は [s]
+// 終点に着いた後は end に従う。既定の drift は、最後の区間を等速 (linear) で進んだときの速度のまま直進する
+// ease_out などで終点の速度が 0 になっても、止まったまま画面に残らないようにするため。止めたいときは hold にする
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Path {
+    // points を順に結ぶ折れ線。durations[i] は points[i] から points[i + 1] までにかける時間
+    Waypoints {
+        points: Vec<Vector2>,
+        durations: Vec<f32>,
+        #[serde(default)]
+        easing: Easing,
+        #[serde(default)]
+        end: PathEnd,
+    },
+    // 3 次ベジェ曲線。points は始点, 制御点, 制御点, 終点
+    Bezier {
+        points: [Vector2; 4],
+        duration: f32,
+        #[serde(default)]
+        easing: Easing,
+        #[serde(default)]
+        end: PathEnd,
+    },
+    // points をすべて通る Catmull-Rom スプライン
+    Spline {
+        points: Vec<Vector2>,
+        duration: f32,
+        #[serde(default)]
+        easing: Easing,
+        #[serde(default)]
+        end: PathEnd,
+    },
+    // vel で進みながら、進行方向と垂直に揺れる
+    Sine {
+        vel: Vector2,
+        amplitude: f32,
+        period: f32,
+    },
+}
+
+impl Path {
+    // 経路が決まっている時間 [s]。Sine は終わらない
+    pub fn duration(&self) -> f32 {
+        match self {
+            Path::Waypoints { durations, .. } => durations.iter().sum(),
+            Path::Bezier { duration, .. } | Path::Spline { duration, .. } => *duration,
+            Path::Sine { .. } => f32::INFINITY,
+        }
+    }
+
+    // 出現から t 秒後の位置
+    pub fn position(&self, t: f32) -> Vector2 {
+        let duration = self.duration();
+        if t > duration {
+            return self.position_on(duration) + self.end_velocity() * (t - duration);
+        }
+        self.position_on(t.max(0.0))
+    }
+
+    // 終点を過ぎた後の速度 [px/s]。easing によらず、最後の区間の等速での速度にする
+    // hold なら 0
+    fn end_velocity(&self) -> Vector2 {
+        match self {
+            Path::Waypoints {
+                end: PathEnd::Hold, ..
+            }
+            | Path::Bezier {
+                end: PathEnd::Hold, ..
+            }
+            | Path::Spline {
+                end: PathEnd::Hold, ..
+            } => Vector2(0.0, 0.0),
+            Path::Waypoints {
+                points, durations, ..
+            } => {
+                let n = points.len();
+                (points[n - 1] - points[n - 2]) / durations[n - 2]
+            }
+            Path::Bezier {
+                points, duration, ..
+            } => (points[3] - points[2]) * 3.0 / *duration,
+            Path::Spline {
+                points, duration, ..
+            } => {
+                // 終点を延長した点で閉じるので、最後の区間の終わりの傾きは (p[n-1] - p[n-2]) / 2
+                let n = points.len();
+                let segments = (n - 1) as f32;
+                (points[n - 1] - points[n - 2]) * 0.5 * segments / *duration
+            }
+            Path::Sine { vel, .. } => *vel,
+        }
+    }
+
+    fn position_on(&self, t: f32) -> Vector2 {
+        match self {
+            Path::Waypoints {
+                points,
+                durations,
+                easing,
+                ..
+            } => {
+                let mut start = 0.0;
+                for (i, &duration) in durations.iter().enumerate() {
+                    if t <= start + duration || i == durations.len() - 1 {
+                        let s = easing.apply(((t - start) / duration).min(1.0));
+                        return points[i] + (points[i + 1] - points[i]) * s;
+                    }
+                    start += duration;
+                }
+                points[0]
+            }
+            Path::Bezier {
+                points,
+                duration,
+                easing,
+                ..
+            } => {
+                let s = easing.apply(t / duration);
+                let r = 1.0 - s;
+                points[0] * (r * r * r)
+                    + points[1] * (3.0 * r * r * s)
+                    + points[2] * (3.0 * r * s * s)
+                    + points[3] * (s * s * s)
+            }
+            Path::Spline {
+                points,
+                duration,
+                easing,
+                ..
+            } => {
+                // 区間の数で等分し、端の区間は端点を延長した点を使う
+                let segments = points.len() - 1;
+                let s = easing.apply(t / duration) * segments as f32;
+                let i = (s.floor() as usize).min(segments - 1);
+                let get = |j: isize| points[j.max(0).min(segments as isize) as usize];
+                let i = i as isize;
+                catmull_rom(get(i - 1), get(i), get(i + 1), get(i + 2), s - i as f32)
+            }
+            Path::Sine {
+                vel,
+                amplitude,
+                period,
+            } => {
+                let speed = vel.norm();
+                let normal = if speed > 0.0 {
+                    Vector2(vel.1, -vel.0) / speed
+                } else {
+                    Vector2(1.0, 0.0)
+                };
+                let phase = 2.0 * std::f32::consts::PI * t / period;
+                *vel * t + normal * (amplitude * phase.sin())
+            }
+        }
+    }
+
+    // 読み込み時に、計算できない経路を弾く
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Path::Waypoints {
+                points, durations, ..
+            } => {
+                if points.len() < 2 || durations.len() != points.len() - 1 {
+                    return Err("waypoints need n points and n - 1 durations".to_string());
+                }
+                if durations.iter().any(|&d| d <= 0.0) {
+                    return Err("waypoint durations must be positive".to_string());
+                }
+            }
+            Path::Bezier { duration, .. } if *duration <= 0.0 => {
+                return Err("bezier duration must be positive".to_string());
+            }
+            Path::Spline {
+                points, duration, ..
+            } => {
+                if points.len() < 2 || *duration <= 0.0 {
+                    return Err("spline needs 2 or more points and a positive duration".to_string());
+                }
+            }
+            Path::Sine { period, .. } if *period <= 0.0 => {
+                return Err("sine period must be positive".to_string());
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// p1 から p2 までを s ∈ [0, 1] で補間する
+fn catmull_rom(p0: Vector2, p1: Vector2, p2: Vector2, p3: Vector2, s: f32) -> Vector2 {
+    let s2 = s * s;
+    let s3 = s2 * s;
+    (p1 * 2.0
+        + (p2 - p0) * s
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * s2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * s3)
+        * 0.5
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PathDef {
+    name: String,
+    #[serde(flatten)]
+    path: Path,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PathFile {
+    path: Vec<PathDef>,
+}
+
+#[derive(Debug, Default)]
+pub struct PathBook {
+    paths: HashMap<String, Path>,
+}
+
+impl PathBook {
+    pub fn new() -> Self {
+        PathBook::default()
+    }
+
+    pub fn load(path: &str) -> Result<PathBook, Box<dyn Error>> {
+        let file: PathFile = toml::from_str(&fs::read_to_string(path)?)?;
+        let mut book = PathBook::new();
+        for def in file.path {
+            def.path
+                .validate()
+                .map_err(|err| format!("path {}: {}", def.name, err))?;
+            if book.paths.insert(def.name.clone(), def.path).is_some() {
+                return Err(format!("duplicate path: {}", def.name).into());
+            }
+        }
+        Ok(book)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Path> {
+        self.paths.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Vector2, expected: Vector2) {
+        assert!(
+            (actual - expected).norm() < 1e-3,
+            "expected ({}, {}), got ({}, {})",
+            expected.0,
+            expected.1,
+            actual.0,
+            actual.1
+        );
+    }
+
+    fn waypoints(easing: Easing) -> Path {
+        Path::Waypoints {
+            points: vec![
+                Vector2(0.0, 0.0),
+                Vector2(100.0, 0.0),
+                Vector2(100.0, -50.0),
+            ],
+            durations: vec![1.0, 0.5],
+            easing: easing,
+            end: PathEnd::Drift,
+        }
+    }
+
+    #[test]
+    fn waypoints_pass_through_every_point() {
+        let path = waypoints(Easing::EaseInOut);
+        assert_near(path.position(0.0), Vector2(0.0, 0.0));
+        assert_near(path.position(1.0), Vector2(100.0, 0.0));
+        assert_near(path.position(1.5), Vector2(100.0, -50.0));
+        // 区間の中では easing がかかる。ease_in_out は中間で半分
+        assert_near(path.position(0.5), Vector2(50.0, 0.0));
+    }
+
+    #[test]
+    fn drift_after_the_end_uses_the_linear_velocity() {
+        for &easing in &[
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            let path = waypoints(easing);
+            // 最後の区間は 0.5 秒で下へ 50 px
+            assert_near(path.end_velocity(), Vector2(0.0, -100.0));
+            assert_near(path.position(2.5), Vector2(100.0, -150.0));
+        }
+    }
+
+    #[test]
+    fn position_is_continuous_at_the_end() {
+        let paths = vec![
+            waypoints(Easing::EaseOut),
+            Path::Bezier {
+                points: [
+                    Vector2(0.0, 0.0),
+                    Vector2(0.0, 100.0),
+                    Vector2(100.0, 100.0),
+                    Vector2(100.0, 0.0),
+                ],
+                duration: 2.0,
+                easing: Easing::EaseInOut,
+                end: PathEnd::Drift,
+            },
+            Path::Spline {
+                points: vec![Vector2(0.0, 0.0), Vector2(50.0, 50.0), Vector2(100.0, 0.0)],
+                duration: 2.0,
+                easing: Easing::EaseOut,
+                end: PathEnd::Drift,
+            },
+        ];
+        for path in &paths {
+            let end = path.duration();
+            let before = path.position(end - 1e-4);
+            let after = path.position(end + 1e-4);
+            assert!((after - before).norm() < 0.1);
+            // 止まったままにならない
+            assert!(path.end_velocity().norm() > 0.0);
+            assert!((path.position(end + 1.0) - path.position(end)).norm() > 0.0);
+        }
+    }
+
+    #[test]
+    fn bezier_end_velocity_follows_the_last_control_point() {
+        let path = Path::Bezier {
+            points: [
+                Vector2(0.0, 0.0),
+                Vector2(0.0, 100.0),
+                Vector2(100.0, 100.0),
+                Vector2(100.0, 0.0),
+            ],
+            duration: 2.0,
+            easing: Easing::Linear,
+            end: PathEnd::Drift,
+        };
+        // 終点での接線は 3 (p3 - p2) / duration
+        assert_near(path.end_velocity(), Vector2(0.0, -150.0));
+        // linear なら終点の前後で速度も変わらない
+        let dt = 1e-2;
+        let before = (path.position(2.0) - path.position(2.0 - dt)) / dt;
+        assert!((before - path.end_velocity()).norm() < 5.0);
+    }
+
+    #[test]
+    fn hold_stops_at_the_end() {
+        let path = Path::Waypoints {
+            points: vec![Vector2(0.0, 0.0), Vector2(0.0, -110.0)],
+            durations: vec![2.0],
+            easing: Easing::EaseOut,
+            end: PathEnd::Hold,
+        };
+        assert_near(path.end_velocity(), Vector2(0.0, 0.0));
+        assert_near(path.position(2.0), Vector2(0.0, -110.0));
+        assert_near(path.position(100.0), Vector2(0.0, -110.0));
+    }
+
+    #[test]
+    fn sine_sways_around_its_velocity() {
+        let path = Path::Sine {
+            vel: Vector2(0.0, -60.0),
+            amplitude: 20.0,
+            period: 2.0,
+        };
+        assert_near(path.position(0.0), Vector2(0.0, 0.0));
+        // 4 分の 1 周期で振れ幅いっぱいに進行方向と垂直にずれる
+        assert_near(path.position(0.5), Vector2(-20.0, -30.0));
+        assert_near(path.position(2.0), Vector2(0.0, -120.0));
+    }
+
+    #[test]
+    fn invalid_paths_are_rejected() {
+        let path = Path::Waypoints {
+            points: vec![Vector2(0.0, 0.0), Vector2(1.0, 0.0)],
+            durations: vec![0.0],
+            easing: Easing::Linear,
+            end: PathEnd::Drift,
+        };
+        assert!(path.validate().is_err());
+        let path = Path::Spline {
+            points: vec![Vector2(0.0, 0.0)],
+            duration: 1.0,
+            easing: Easing::Linear,
+            end: PathEnd::Drift,
+        };
+        assert!(path.validate().is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::pattern_mods::expr::Expr;
+use crate::pattern_mods::pattern::{DirectionType, Op, PatternBook, SpeedType};
+
+// BulletML (http://www.asahi-net.or.jp/~cs8k-cyu/bulletml/) を PatternBook の命令列に変換する
+//
+// - 参照 (actionRef, bulletRef, fireRef) は引数を式のまま埋め込んで展開する。
+//   そのため引数中の $rand は参照先で使われるたびに評価し直される
+// - 弾の中に複数の <action> がある場合は並行ではなく順に実行する
+// - 単位は [px/frame], [frame], [deg] から [px/s], [s], [rad] に直す
+const FRAME: f32 = 1.0 / 60.0;
+const FPS: f32 = 60.0;
+// 参照の展開の深さの上限。自分自身を参照する定義を弾く
+const MAX_REF_DEPTH: usize = 32;
+
+// dir にある *.xml をファイル名 (拡張子なし) で登録する
+pub fn import_dir(book: &mut PatternBook, dir: &str) -> Result<(), Box<dyn Error>> {
+    if !Path::new(dir).is_dir() {
+        return Ok(());
+    }
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "xml"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        import(book, name, &fs::read_to_string(&path)?)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+// "top" で始まるラベルの <action> をそれぞれ並行して動く命令列として name に登録する
+pub fn import(book: &mut PatternBook, name: &str, xml: &str) -> Result<(), Box<dyn Error>> {
+    let doc = Document::parse(xml)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "bulletml" {
+        return Err("root element must be <bulletml>".into());
+    }
+    let mut importer = Importer {
+        labels: HashMap::new(),
+        empty_program: book.add_program(Vec::new())?,
+        depth: 0,
+    };
+    for node in root.children().filter(|n| n.is_element()) {
+        if let Some(label) = node.attribute("label") {
+            importer
+                .labels
+                .insert((node.tag_name().name(), label), node);
+        }
+    }
+
+    let mut tops: Vec<_> = importer
+        .labels
+        .iter()
+        .filter(|((tag, label), _)| *tag == "action" && label.starts_with("top"))
+        .map(|((_, label), node)| (*label, *node))
+        .collect();
+    tops.sort_by_key(|(label, _)| *label);
+    if tops.is_empty() {
+        return Err("no top action".into());
+    }
+    let mut programs = Vec::new();
+    for (_, node) in tops {
+        let mut ops = Vec::new();
+        importer.compile_action(book, node, &[], &mut ops)?;
+        let program = book.add_program(ops)?;
+        book.validate(program)?;
+        programs.push(program);
+    }
+    book.register(name, programs)?;
+    Ok(())
+}
+
+struct Importer<'a, 'input> {
+    // (要素名, ラベル) → 定義
+    labels: HashMap<(&'a str, &'a str), Node<'a, 'input>>,
+    // 動きを持たない弾に持たせる空の命令列
+    empty_program: usize,
+    depth: usize,
+}
+
+impl<'a, 'input> Importer<'a, 'input> {
+    fn compile_action(
+        &mut self,
+        book: &mut PatternBook,
+        node: Node<'a, 'input>,
+        params: &[Expr],
+        ops: &mut Vec<Op>,
+    ) -> Result<(), String> {
+        for child in node.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "repeat" => {
+                    let times = expr_of(required(child, "times")?, params)?;
+                    let start = ops.len();
+                    ops.push(Op::RepeatEnd);
+                    for body in child.children().filter(|n| n.is_element()) {
+                        match body.tag_name().name() {
+                            "action" | "actionRef" => self.compile_ref(book, body, params, ops)?,
+                            "times" => (),
+                            other => return Err(format!("unexpected <{}> in <repeat>", other)),
+                        }
+                    }
+                    ops.push(Op::RepeatEnd);
+                    ops[start] = Op::RepeatStart {
+                        times: times,
+                        end: ops.len(),
+                    };
+                }
+                "fire" => self.compile_fire(book, child, params, ops)?,
+                "fireRef" => {
+                    let (fire, args) = self.resolve(child, "fire", params)?;
+                    self.enter()?;
+                    self.compile_fire(book, fire, &args, ops)?;
+                    self.depth -= 1;
+                }
+                "changeDirection" => {
+                    let (kind, value) = direction_of(required(child, "direction")?, params, true)?;
+                    ops.push(Op::ChangeDirection {
+                        kind: kind,
+                        value: value,
+                        term: expr_of(required(child, "term")?, params)?.scaled(FRAME),
+                    });
+                }
+                "changeSpeed" => {
+                    let (kind, value) = speed_of(required(child, "speed")?, params, true)?;
+                    ops.push(Op::ChangeSpeed {
+                        kind: kind,
+                        value: value,
+                        term: expr_of(required(child, "term")?, params)?.scaled(FRAME),
+                    });
+                }
+                "accel" => {
+                    let horizontal = match child_element(child, "horizontal") {
+                        Some(n) => Some(speed_of(n, params, true)?),
+                        None => None,
+                    };
+                    // BulletML の縦方向は画面下向きが正
+                    let vertical = match child_element(child, "vertical") {
+                        Some(n) => {
+                            let (kind, value) = speed_of(n, params, true)?;
+                            Some((kind, value.scaled(-1.0)))
+                        }
+                        None => None,
+                    };
+                    ops.push(Op::Accel {
+                        horizontal: horizontal,
+                        vertical: vertical,
+                        term: expr_of(required(child, "term")?, params)?.scaled(FRAME),
+                    });
+                }
+                "wait" => ops.push(Op::Wait(expr_of(child, params)?.scaled(FRAME))),
+                "vanish" => ops.push(Op::Vanish),
+                "action" | "actionRef" => self.compile_ref(book, child, params, ops)?,
+                other => return Err(format!("unexpected <{}> in <action>", other)),
+            }
+        }
+        Ok(())
+    }
+
+    // <action> ならその場で、<actionRef> なら参照先を展開する
+    fn compile_ref(
+        &mut self,
+        book: &mut PatternBook,
+        node: Node<'a, 'input>,
+        params: &[Expr],
+        ops: &mut Vec<Op>,
+    ) -> Result<(), String> {
+        if node.tag_name().name() == "action" {
+            return self.compile_action(book, node, params, ops);
+        }
+        let (action, args) = self.resolve(node, "action", params)?;
+        self.enter()?;
+        self.compile_action(book, action, &args, ops)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn compile_fire(
+        &mut self,
+        book: &mut PatternBook,
+        node: Node<'a, 'input>,
+        params: &[Expr],
+        ops: &mut Vec<Op>,
+    ) -> Result<(), String> {
+        let (bullet, bullet_params) = match child_element(node, "bullet") {
+            Some(bullet) => (bullet, params.to_vec()),
+            None => {
+                let bullet_ref = child_element(node, "bulletRef")
+                    .ok_or_else(|| "<fire> needs <bullet> or <bulletRef>".to_string())?;
+                self.resolve(bullet_ref, "bullet", params)?
+            }
+        };
+        self.enter()?;
+
+        // <fire> の指定がなければ <bullet> の指定を使う
+        let direction = match child_element(node, "direction") {
+            Some(n) => Some(direction_of(n, params, false)?),
+            None => match child_element(bullet, "direction") {
+                Some(n) => Some(direction_of(n, &bullet_params, false)?),
+                None => None,
+            },
+        };
+        let speed = match child_element(node, "speed") {
+            Some(n) => Some(speed_of(n, params, false)?),
+            None => match child_element(bullet, "speed") {
+                Some(n) => Some(speed_of(n, &bullet_params, false)?),
+                None => None,
+            },
+        };
+
+        let mut bullet_ops = Vec::new();
+        for child in bullet.children().filter(|n| n.is_element()) {
+            if let "action" | "actionRef" = child.tag_name().name() {
+                self.compile_ref(book, child, &bullet_params, &mut bullet_ops)?;
+            }
+        }
+        let program = if bullet_ops.is_empty() {
+            self.empty_program
+        } else {
+            let program = book.add_program(bullet_ops)?;
+            book.validate(program)?;
+            program
+        };
+        self.depth -= 1;
+
+        ops.push(Op::FireBullet {
+            direction: direction,
+            speed: speed,
+            program: Some(program),
+        });
+        Ok(())
+    }
+
+    // 参照先の定義と、<param> で渡される引数
+    fn resolve(
+        &self,
+        node: Node<'a, 'input>,
+        tag: &str,
+        params: &[Expr],
+    ) -> Result<(Node<'a, 'input>, Vec<Expr>), String> {
+        let label = node
+            .attribute("label")
+            .ok_or_else(|| format!("<{}> without label", node.tag_name().name()))?;
+        let target = *self
+            .labels
+            .get(&(tag, label))
+            .ok_or_else(|| format!("unknown {}: {}", tag, label))?;
+        let args = node
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() == "param")
+            .map(|n| expr_of(n, params))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((target, args))
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_REF_DEPTH {
+            return Err("references nested too deeply".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn child_element<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn required<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>, String> {
+    child_element(node, name)
+        .ok_or_else(|| format!("<{}> needs <{}>", node.tag_name().name(), name))
+}
+
+fn expr_of(node: Node, params: &[Expr]) -> Result<Expr, String> {
+    Ok(Expr::parse(node.text().unwrap_or("0").trim())?.substitute(params))
+}
+
+// per_frame が真なら sequence の値を 1 フレームあたりの変化量として扱う
+fn direction_of(
+    node: Node,
+    params: &[Expr],
+    per_frame: bool,
+) -> Result<(DirectionType, Expr), String> {
+    let kind = match node.attribute("type").unwrap_or("aim") {
+        "aim" => DirectionType::Aim,
+        "absolute" => DirectionType::Absolute,
+        "relative" => DirectionType::Relative,
+        "sequence" => DirectionType::Sequence,
+        other => return Err(format!("unknown direction type: {}", other)),
+    };
+    let mut value = expr_of(node, params)?.scaled(std::f32::consts::PI / 180.0);
+    if per_frame && kind == DirectionType::Sequence {
+        value = value.scaled(FPS);
+    }
+    Ok((kind, value))
+}
+
+fn speed_of(node: Node, params: &[Expr], per_frame: bool) -> Result<(SpeedType, Expr), String> {
+    let kind = match node.attribute("type").unwrap_or("absolute") {
+        "absolute" => SpeedType::Absolute,
+        "relative" => SpeedType::Relative,
+        "sequence" => SpeedType::Sequence,
+        other => return Err(format!("unknown speed type: {}", other)),
+    };
+    let mut value = expr_of(node, params)?.scaled(FPS);
+    if per_frame && kind == SpeedType::Sequence {
+        value = value.scaled(FPS);
+    }
+    Ok((kind, value))
+}
@@ -0,0 +1,177 @@
+use oorandom::Rand32;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// パターン中の数式。BulletML の $rank, $rand, $1 ... を扱う
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Num(f32),
+    // 難易度 [0, 1]
+    Rank,
+    // 評価するたびに変わる [0, 1) の乱数
+    Rand,
+    // 参照元から渡される引数 ($1 が 0 番)
+    Param(usize),
+    Neg(Box<Expr>),
+    Bin(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, String> {
+        let mut chars = s.chars().peekable();
+        let expr = parse_sum(&mut chars)?;
+        skip_spaces(&mut chars);
+        match chars.next() {
+            None => Ok(expr),
+            Some(c) => Err(format!("unexpected '{}' in expression: {}", c, s)),
+        }
+    }
+
+    pub fn scaled(self, k: f32) -> Expr {
+        Expr::Bin(Box::new(self), BinOp::Mul, Box::new(Expr::Num(k)))
+    }
+
+    pub fn eval(&self, rank: f32, rng: &mut Rand32) -> f32 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Rank => rank,
+            Expr::Rand => rng.rand_float(),
+            // 引数は展開済みのはずなので、残っていれば 0 とみなす
+            Expr::Param(_) => 0.0,
+            Expr::Neg(e) => -e.eval(rank, rng),
+            Expr::Bin(l, op, r) => {
+                let (l, r) = (l.eval(rank, rng), r.eval(rank, rng));
+                match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    BinOp::Mod => l % r,
+                }
+            }
+        }
+    }
+
+    // $1, $2 ... を params で置き換える
+    pub fn substitute(&self, params: &[Expr]) -> Expr {
+        match self {
+            Expr::Param(i) => params.get(*i).cloned().unwrap_or(Expr::Num(0.0)),
+            Expr::Neg(e) => Expr::Neg(Box::new(e.substitute(params))),
+            Expr::Bin(l, op, r) => Expr::Bin(
+                Box::new(l.substitute(params)),
+                *op,
+                Box::new(r.substitute(params)),
+            ),
+            e => e.clone(),
+        }
+    }
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_sum(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
+    let mut expr = parse_product(chars)?;
+    loop {
+        skip_spaces(chars);
+        let op = match chars.peek() {
+            Some('+') => BinOp::Add,
+            Some('-') => BinOp::Sub,
+            _ => return Ok(expr),
+        };
+        chars.next();
+        expr = Expr::Bin(Box::new(expr), op, Box::new(parse_product(chars)?));
+    }
+}
+
+fn parse_product(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
+    let mut expr = parse_unary(chars)?;
+    loop {
+        skip_spaces(chars);
+        let op = match chars.peek() {
+            Some('*') => BinOp::Mul,
+            Some('/') => BinOp::Div,
+            Some('%') => BinOp::Mod,
+            _ => return Ok(expr),
+        };
+        chars.next();
+        expr = Expr::Bin(Box::new(expr), op, Box::new(parse_unary(chars)?));
+    }
+}
+
+fn parse_unary(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
+    skip_spaces(chars);
+    match chars.peek() {
+        Some('-') => {
+            chars.next();
+            Ok(Expr::Neg(Box::new(parse_unary(chars)?)))
+        }
+        Some('+') => {
+            chars.next();
+            parse_unary(chars)
+        }
+        _ => parse_primary(chars),
+    }
+}
+
+fn parse_primary(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
+    skip_spaces(chars);
+    match chars.peek().copied() {
+        Some('(') => {
+            chars.next();
+            let expr = parse_sum(chars)?;
+            skip_spaces(chars);
+            match chars.next() {
+                Some(')') => Ok(expr),
+                _ => Err("missing ')' in expression".to_string()),
+            }
+        }
+        Some('$') => {
+            chars.next();
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_alphanumeric() {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            match name.as_str() {
+                "rank" => Ok(Expr::Rank),
+                "rand" => Ok(Expr::Rand),
+                _ => match name.parse::<usize>() {
+                    Ok(i) if i >= 1 => Ok(Expr::Param(i - 1)),
+                    _ => Err(format!("unknown variable: ${}", name)),
+                },
+            }
+        }
+        Some(c) if c.is_ascii_digit() || c == '.' => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            number
+                .parse()
+                .map(Expr::Num)
+                .map_err(|_| format!("invalid number: {}", number))
+        }
+        Some(c) => Err(format!("unexpected '{}' in expression", c)),
+        None => Err("unexpected end of expression".to_string()),
+    }
+}
@@ -0,0 +1,4 @@
+pub mod bulletml;
+pub mod expr;
+pub mod pattern;
+pub mod pattern_runner;
@@ -0,0 +1,570 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use oorandom::Rand32;
+
+use crate::pattern_mods::expr::Expr;
+use std::error::Error;
+use std::fs;
+
+// 繰り返しと呼び出しの入れ子の深さの上限
+pub const MAX_DEPTH: usize = 8;
+// PatternRunner は命令列の番号と位置を u16 で持つので、命令列の数と長さはこれ以下にする
+pub const MAX_PROGRAM_LEN: usize = u16::MAX as usize;
+
+// 弾幕パターンの記述。data/patterns.toml から読み込む
+// 角度は度数法で、0 が画面上方向、時計回りが正
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    // 全方位に等間隔の弾
+    Ring {
+        count: u32,
+        speed: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        ang_vel: f32,
+        #[serde(default = "default_size")]
+        size: f32,
+        // 撃った弾に持たせるパターンの名前
+        #[serde(default)]
+        script: Option<String>,
+    },
+    // angle を中心に spread の範囲へ広がる弾
+    Fan {
+        count: u32,
+        spread: f32,
+        speed: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        aimed: bool,
+        // aimed と合わせて、自機の移動先を狙う
+        #[serde(default)]
+        lead: bool,
+        #[serde(default)]
+        ang_vel: f32,
+        #[serde(default = "default_size")]
+        size: f32,
+        // 撃った弾に持たせるパターンの名前
+        #[serde(default)]
+        script: Option<String>,
+    },
+    // 自機を狙う 1 発
+    Aimed {
+        speed: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        lead: bool,
+        #[serde(default = "default_size")]
+        size: f32,
+        // 撃った弾に持たせるパターンの名前
+        #[serde(default)]
+        script: Option<String>,
+    },
+    // 相手を追いかける弾。turn_rate [度/s] まで曲がり、duration 秒で誘導をやめる
+    Homing {
+        speed: f32,
+        turn_rate: f32,
+        #[serde(default = "default_count")]
+        count: u32,
+        #[serde(default)]
+        spread: f32,
+        #[serde(default = "default_duration")]
+        duration: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        aimed: bool,
+        #[serde(default = "default_size")]
+        size: f32,
+    },
+    Laser {
+        length: f32,
+        width: f32,
+        warn_time: f32,
+        duration: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        aimed: bool,
+        #[serde(default)]
+        ang_vel: f32,
+    },
+    Beams {
+        count: u32,
+        length: f32,
+        width: f32,
+        warn_time: f32,
+        duration: f32,
+        #[serde(default)]
+        ang_vel: f32,
+    },
+    Snake {
+        speed: f32,
+        segments: u32,
+        interval: f32,
+        width: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        aimed: bool,
+        #[serde(default)]
+        ang_vel: f32,
+    },
+    Wait {
+        time: f32,
+    },
+    // 以降に撃つ弾の角度・速さをずらす
+    AngleSweep {
+        delta: f32,
+    },
+    SpeedSweep {
+        delta: f32,
+    },
+    Repeat {
+        times: u32,
+        commands: Vec<Command>,
+    },
+    // 別のパターンを入れ子で実行する
+    Call {
+        pattern: String,
+    },
+    // 以降は弾に持たせるパターン (script) 用の命令
+    // time 秒かけて speed まで加速・減速する
+    Accel {
+        speed: f32,
+        #[serde(default)]
+        time: f32,
+    },
+    // time 秒かけて angle の方向へ向きを変える
+    // aimed なら自機の方向、relative なら今の向きを angle = 0 とする
+    Turn {
+        angle: f32,
+        #[serde(default)]
+        time: f32,
+        #[serde(default)]
+        aimed: bool,
+        #[serde(default)]
+        relative: bool,
+    },
+    // 以降 count 回まで画面端で跳ね返る
+    Bounce {
+        count: u32,
+    },
+    Vanish,
+}
+
+fn default_size() -> f32 {
+    4.0
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+fn default_duration() -> f32 {
+    f32::INFINITY
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PatternDef {
+    name: String,
+    commands: Vec<Command>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PatternFile {
+    pattern: Vec<PatternDef>,
+}
+
+// 入れ子を展開した命令列
+// 角度は [rad]、速さは [px/s]、時間は [s] に揃えてある
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    // 撃った弾には 2 つ目の命令列を持たせる
+    Fire(Command, Option<usize>),
+    // BulletML の <fire>。program は撃った弾に持たせる命令列
+    FireBullet {
+        direction: Option<(DirectionType, Expr)>,
+        speed: Option<(SpeedType, Expr)>,
+        program: Option<usize>,
+    },
+    Wait(Expr),
+    AngleSweep(f32),
+    SpeedSweep(f32),
+    // end は対応する RepeatEnd の次の位置
+    RepeatStart {
+        times: Expr,
+        end: usize,
+    },
+    RepeatEnd,
+    Call(usize),
+    // term の間に向き・速さ・加速度を変える。Sequence の値は 1 秒あたりの変化量
+    ChangeDirection {
+        kind: DirectionType,
+        value: Expr,
+        term: Expr,
+    },
+    ChangeSpeed {
+        kind: SpeedType,
+        value: Expr,
+        term: Expr,
+    },
+    Accel {
+        horizontal: Option<(SpeedType, Expr)>,
+        vertical: Option<(SpeedType, Expr)>,
+        term: Expr,
+    },
+    Bounce(u32),
+    Vanish,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DirectionType {
+    Aim,
+    Absolute,
+    Relative,
+    Sequence,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpeedType {
+    Absolute,
+    Relative,
+    Sequence,
+}
+
+#[derive(Debug, Default)]
+pub struct PatternBook {
+    programs: Vec<Vec<Op>>,
+    // 1 つの名前に並行して動く複数の命令列を登録できる
+    names: HashMap<String, Vec<usize>>,
+}
+
+impl PatternBook {
+    pub fn new() -> Self {
+        PatternBook::default()
+    }
+
+    pub fn load(path: &str) -> Result<PatternBook, Box<dyn Error>> {
+        PatternBook::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<PatternBook, Box<dyn Error>> {
+        let file: PatternFile = toml::from_str(text)?;
+        let mut book = PatternBook::new();
+        for (i, def) in file.pattern.iter().enumerate() {
+            book.register(&def.name, vec![i])?;
+        }
+        for def in &file.pattern {
+            let mut ops = Vec::new();
+            book.compile(&def.commands, &mut ops)?;
+            book.add_program(ops)?;
+        }
+        for i in 0..book.programs.len() {
+            book.validate(i)?;
+        }
+        Ok(book)
+    }
+
+    pub fn add_program(&mut self, ops: Vec<Op>) -> Result<usize, String> {
+        if ops.len() > MAX_PROGRAM_LEN {
+            return Err(format!("pattern too long: {} ops", ops.len()));
+        }
+        if self.programs.len() >= MAX_PROGRAM_LEN {
+            return Err(format!("too many patterns: {}", self.programs.len()));
+        }
+        self.programs.push(ops);
+        Ok(self.programs.len() - 1)
+    }
+
+    pub fn register(&mut self, name: &str, programs: Vec<usize>) -> Result<(), String> {
+        if self.names.insert(name.to_string(), programs).is_some() {
+            return Err(format!("duplicate pattern: {}", name));
+        }
+        Ok(())
+    }
+
+    pub fn get_program(&self, index: usize) -> &[Op] {
+        &self.programs[index]
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.find_all(name)
+            .and_then(|programs| programs.first().copied())
+    }
+
+    pub fn find_all(&self, name: &str) -> Option<&[usize]> {
+        self.names.get(name).map(|programs| programs.as_slice())
+    }
+
+    // 敵に持たせて繰り返し実行するパターン。1 周ごとに必ず待つものだけを返す
+    // 待たずに繰り返すと、毎ステップ命令数の上限まで弾を撃ち続けてしまう
+    pub fn find_looping(&self, name: &str) -> Result<&[usize], String> {
+        let programs = self
+            .find_all(name)
+            .ok_or_else(|| format!("unknown pattern: {}", name))?;
+        for &program in programs {
+            if !self.always_waits(program) {
+                return Err(format!("pattern {} loops without wait", name));
+            }
+        }
+        Ok(programs)
+    }
+
+    // 実行が終わらないパターンや、入れ子が深すぎるパターンを弾く
+    pub fn validate(&self, index: usize) -> Result<(), String> {
+        self.check_recursion(index, &mut Vec::new())?;
+        let (loops, calls) = self.depth(index);
+        if loops > MAX_DEPTH || calls > MAX_DEPTH {
+            return Err(format!(
+                "pattern nested too deeply: {:?}",
+                self.name_of(index)
+            ));
+        }
+        Ok(())
+    }
+
+    fn compile(&self, commands: &[Command], ops: &mut Vec<Op>) -> Result<(), String> {
+        for command in commands {
+            match command {
+                Command::Wait { time } => ops.push(Op::Wait(Expr::Num(*time))),
+                Command::AngleSweep { delta } => ops.push(Op::AngleSweep(*delta)),
+                Command::SpeedSweep { delta } => ops.push(Op::SpeedSweep(*delta)),
+                Command::Repeat { times, commands } => {
+                    if *times == 0 {
+                        return Err("repeat times must be positive".to_string());
+                    }
+                    let start = ops.len();
+                    ops.push(Op::RepeatEnd);
+                    self.compile(commands, ops)?;
+                    ops.push(Op::RepeatEnd);
+                    ops[start] = Op::RepeatStart {
+                        times: Expr::Num(*times as f32),
+                        end: ops.len(),
+                    };
+                }
+                Command::Call { pattern } => {
+                    let index = self
+                        .find(pattern)
+                        .ok_or_else(|| format!("unknown pattern: {}", pattern))?;
+                    ops.push(Op::Call(index));
+                }
+                Command::Accel { speed, time } => ops.push(Op::ChangeSpeed {
+                    kind: SpeedType::Absolute,
+                    value: Expr::Num(*speed),
+                    term: Expr::Num(*time),
+                }),
+                Command::Turn {
+                    angle,
+                    time,
+                    aimed,
+                    relative,
+                } => {
+                    let kind = match (*aimed, *relative) {
+                        (true, false) => DirectionType::Aim,
+                        (false, true) => DirectionType::Relative,
+                        (false, false) => DirectionType::Absolute,
+                        (true, true) => {
+                            return Err("turn cannot be both aimed and relative".to_string())
+                        }
+                    };
+                    ops.push(Op::ChangeDirection {
+                        kind: kind,
+                        value: Expr::Num(angle.to_radians()),
+                        term: Expr::Num(*time),
+                    });
+                }
+                Command::Bounce { count } => ops.push(Op::Bounce(*count)),
+                Command::Vanish => ops.push(Op::Vanish),
+                Command::Ring { script, .. }
+                | Command::Fan { script, .. }
+                | Command::Aimed { script, .. } => {
+                    let program = match script {
+                        Some(name) => Some(
+                            self.find(name)
+                                .ok_or_else(|| format!("unknown pattern: {}", name))?,
+                        ),
+                        None => None,
+                    };
+                    ops.push(Op::Fire(command.clone(), program));
+                }
+                _ => ops.push(Op::Fire(command.clone(), None)),
+            }
+        }
+        Ok(())
+    }
+
+    // 自分自身を呼び出すパターンは実行が終わらないので読み込み時に弾く
+    fn check_recursion(&self, index: usize, path: &mut Vec<usize>) -> Result<(), String> {
+        if path.contains(&index) {
+            return Err(format!("recursive pattern: {:?}", self.name_of(index)));
+        }
+        path.push(index);
+        for op in &self.programs[index] {
+            match op {
+                // 弾が自分と同じ弾を撃ち続けると数が際限なく増えるので、弾のパターンもたどる
+                Op::Call(callee) | Op::Fire(_, Some(callee)) => {
+                    self.check_recursion(*callee, path)?
+                }
+                _ => (),
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+
+    // 1 回実行するたびに、どの経路を通っても時間の経つ Wait に着くか
+    // 式は rank が 0 と 1 のときの小さい方で判断する
+    fn always_waits(&self, index: usize) -> bool {
+        let ops = &self.programs[index];
+        let mut pc = 0;
+        while pc < ops.len() {
+            match &ops[pc] {
+                Op::Wait(time) if min_value(time) > 0.0 => return true,
+                Op::Call(callee) if self.always_waits(*callee) => return true,
+                // 1 回も回らないかもしれない繰り返しは、中身を飛ばしたものとみなす
+                Op::RepeatStart { times, end } if min_value(times) < 1.0 => {
+                    pc = *end;
+                    continue;
+                }
+                _ => (),
+            }
+            pc += 1;
+        }
+        false
+    }
+
+    // (繰り返しの入れ子の深さ, 呼び出しの入れ子の深さ)
+    fn depth(&self, index: usize) -> (usize, usize) {
+        let mut loops = 0;
+        let mut max = (0, 0);
+        for op in &self.programs[index] {
+            match op {
+                Op::RepeatStart { .. } => {
+                    loops += 1;
+                    max.0 = max.0.max(loops);
+                }
+                Op::RepeatEnd => loops -= 1,
+                Op::Call(callee) => {
+                    let (callee_loops, callee_calls) = self.depth(*callee);
+                    max.0 = max.0.max(loops + callee_loops);
+                    max.1 = max.1.max(callee_calls + 1);
+                }
+                _ => (),
+            }
+        }
+        max
+    }
+
+    fn name_of(&self, index: usize) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, programs)| programs.contains(&index))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+// 難易度の両端で評価した式の小さい方。乱数は固定の系列で評価する
+fn min_value(expr: &Expr) -> f32 {
+    let mut rng = Rand32::new(0);
+    expr.eval(0.0, &mut rng).min(expr.eval(1.0, &mut rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(text: &str) -> PatternBook {
+        PatternBook::from_toml(text).unwrap()
+    }
+
+    #[test]
+    fn looping_pattern_must_wait() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "no_wait"
+            commands = [{ type = "ring", count = 8, speed = 100.0 }]
+
+            [[pattern]]
+            name = "zero_wait"
+            commands = [{ type = "ring", count = 8, speed = 100.0 }, { type = "wait", time = 0.0 }]
+
+            [[pattern]]
+            name = "waits"
+            commands = [{ type = "ring", count = 8, speed = 100.0 }, { type = "wait", time = 0.5 }]
+            "#,
+        );
+        assert!(book.find_looping("no_wait").is_err());
+        assert!(book.find_looping("zero_wait").is_err());
+        assert!(book.find_looping("waits").is_ok());
+        assert!(book.find_looping("missing").is_err());
+        // 弾に持たせるだけなら待たなくてよい
+        assert!(book.find_all("no_wait").is_some());
+    }
+
+    #[test]
+    fn wait_inside_repeat_or_call_counts() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "pause"
+            commands = [{ type = "wait", time = 0.1 }]
+
+            [[pattern]]
+            name = "calls_pause"
+            commands = [{ type = "aimed", speed = 100.0 }, { type = "call", pattern = "pause" }]
+
+            [[pattern]]
+            name = "repeated"
+            commands = [
+                { type = "repeat", times = 3, commands = [
+                    { type = "aimed", speed = 100.0 },
+                    { type = "wait", time = 0.1 },
+                ] },
+            ]
+            "#,
+        );
+        assert!(book.find_looping("calls_pause").is_ok());
+        assert!(book.find_looping("repeated").is_ok());
+    }
+
+    #[test]
+    fn repeat_that_may_not_run_does_not_count() {
+        let mut book = PatternBook::new();
+        // rank が 0 のとき 0 回になる繰り返しの中にしか Wait がない
+        let ops = vec![
+            Op::RepeatStart {
+                times: Expr::Rank.scaled(4.0),
+                end: 3,
+            },
+            Op::Wait(Expr::Num(0.1)),
+            Op::RepeatEnd,
+        ];
+        let program = book.add_program(ops).unwrap();
+        book.register("rank_repeat", vec![program]).unwrap();
+        assert!(book.find_looping("rank_repeat").is_err());
+    }
+
+    #[test]
+    fn recursion_is_rejected() {
+        let mut book = PatternBook::new();
+        let a = book.add_program(vec![Op::Call(1)]).unwrap();
+        let b = book.add_program(vec![Op::Call(0)]).unwrap();
+        book.register("a", vec![a]).unwrap();
+        book.register("b", vec![b]).unwrap();
+        assert!(book.validate(a).is_err());
+    }
+
+    #[test]
+    fn program_length_is_limited_to_the_runner_fields() {
+        let mut book = PatternBook::new();
+        let ops = vec![Op::Vanish; MAX_PROGRAM_LEN + 1];
+        assert!(book.add_program(ops).is_err());
+        assert!(book.add_program(vec![Op::Vanish; MAX_PROGRAM_LEN]).is_ok());
+    }
+}
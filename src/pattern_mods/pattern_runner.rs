@@ -0,0 +1,694 @@
+use oorandom::Rand32;
+
+use crate::actor_mods::actor::*;
+use crate::difficulty::{rank_count, rank_speed};
+use crate::pattern_mods::expr::Expr;
+use crate::pattern_mods::pattern::{Command, DirectionType, Op, PatternBook, SpeedType, MAX_DEPTH};
+use crate::vector2::{normalize_angle, vec_from_angle, Vector2};
+
+// 待ち時間を含まないパターンでも 1 ステップで止まるようにする上限
+const MAX_OPS_PER_STEP: usize = 1024;
+// 速さの指定がない弾の速さ [px/s] (BulletML の既定値 1 [px/frame])
+const DEFAULT_SPEED: f32 = 60.0;
+const DEFAULT_SIZE: f32 = 4.0;
+// 変化にかける時間の下限 (1 フレーム)
+const MIN_TERM: f32 = 1.0 / 60.0;
+
+// 弾を撃つ側の状況
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PatternContext {
+    pub origin: Vector2,
+    // 狙う相手 (自機) の位置と速度 [px/s]
+    pub target: Vector2,
+    pub target_vel: Vector2,
+    // 難易度 [0, 1]
+    pub rank: f32,
+}
+
+impl PatternContext {
+    // 相手の今の位置を向く方向
+    pub fn aim_angle(&self) -> f32 {
+        aim_angle(self.origin, self.target)
+    }
+
+    // 速さ speed の弾が相手の移動先で当たる方向
+    pub fn lead_angle(&self, speed: f32) -> f32 {
+        lead_angle(self.origin, self.target, self.target_vel, speed)
+    }
+}
+
+// PatternBook の命令列を実行する。弾にも持たせるので状態は固定長で Copy にしておく
+// 弾に持たせた場合は、向きと速さ (direction, speed) がそのまま弾の速度になる
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PatternRunner {
+    program: u16,
+    pc: u16,
+    wait: f32,
+    // AngleSweep, SpeedSweep による累積のずれ
+    sweep_angle: f32,
+    sweep_speed: f32,
+    // (繰り返しの先頭, 残り回数)
+    loops: [(u16, u32); MAX_DEPTH],
+    loop_depth: u8,
+    // (呼び出し元のパターン, 戻り先)
+    calls: [(u16, u16); MAX_DEPTH],
+    call_depth: u8,
+    direction: f32,
+    speed: f32,
+    accel_vel: Vector2,
+    // 進行中の変化 (1 秒あたりの変化量, 残り時間)
+    turn: (f32, f32),
+    speed_change: (f32, f32),
+    accel: (Vector2, f32),
+    // 直前に撃った弾の向きと速さ (sequence 指定の基準)
+    last_direction: f32,
+    last_speed: f32,
+    // 画面端で跳ね返れる残り回数
+    bounces: u32,
+    // 最後まで実行したら先頭から繰り返すか
+    looping: bool,
+    vanished: bool,
+}
+
+impl PatternRunner {
+    // 敵に持たせる場合。向きは画面下向きで、パターンは繰り返す
+    pub fn new(program: usize) -> Self {
+        PatternRunner {
+            looping: true,
+            ..PatternRunner::for_bullet(program, std::f32::consts::PI, 0.0)
+        }
+    }
+
+    pub fn for_bullet(program: usize, direction: f32, speed: f32) -> Self {
+        PatternRunner {
+            program: program as u16,
+            pc: 0,
+            wait: 0.0,
+            sweep_angle: 0.0,
+            sweep_speed: 0.0,
+            loops: [(0, 0); MAX_DEPTH],
+            loop_depth: 0,
+            calls: [(0, 0); MAX_DEPTH],
+            call_depth: 0,
+            direction: direction,
+            speed: speed,
+            accel_vel: Vector2(0.0, 0.0),
+            turn: (0.0, 0.0),
+            speed_change: (0.0, 0.0),
+            accel: (Vector2(0.0, 0.0), 0.0),
+            last_direction: direction,
+            last_speed: speed,
+            bounces: 0,
+            looping: false,
+            vanished: false,
+        }
+    }
+
+    pub fn velocity(&self) -> Vector2 {
+        vec_from_angle(self.direction) * self.speed + self.accel_vel
+    }
+
+    pub fn is_vanished(&self) -> bool {
+        self.vanished
+    }
+
+    pub fn get_bounces(&self) -> u32 {
+        self.bounces
+    }
+
+    // 壁で跳ね返す。horizontal は左右の壁、vertical は上下の壁
+    pub fn reflect(&mut self, horizontal: bool, vertical: bool) {
+        if horizontal {
+            self.direction = -self.direction;
+            self.accel_vel.0 = -self.accel_vel.0;
+            self.turn.0 = -self.turn.0;
+        }
+        if vertical {
+            self.direction = std::f32::consts::PI - self.direction;
+            self.accel_vel.1 = -self.accel_vel.1;
+            self.turn.0 = -self.turn.0;
+        }
+        self.bounces = self.bounces.saturating_sub(1);
+    }
+
+    // dt だけ時間を進め、その間に撃たれた弾を shots に追加する
+    pub fn update(
+        &mut self,
+        book: &PatternBook,
+        dt: f32,
+        context: &PatternContext,
+        rng: &mut Rand32,
+        shots: &mut Vec<Actor>,
+    ) {
+        if self.vanished {
+            return;
+        }
+        self.update_changes(dt);
+        self.wait -= dt;
+        let mut budget = MAX_OPS_PER_STEP;
+        while self.wait <= 0.0 && budget > 0 && !self.vanished {
+            budget -= 1;
+            let ops = book.get_program(self.program as usize);
+            let pc = self.pc as usize;
+            if pc >= ops.len() {
+                if self.call_depth > 0 {
+                    self.call_depth -= 1;
+                    let (program, pc) = self.calls[self.call_depth as usize];
+                    self.program = program;
+                    self.pc = pc;
+                } else if ops.is_empty() || !self.looping {
+                    break;
+                } else {
+                    self.pc = 0;
+                }
+                continue;
+            }
+            self.pc += 1;
+            let rank = context.rank;
+            match &ops[pc] {
+                Op::Fire(command, program) => self.fire(command, *program, context, shots),
+                Op::FireBullet {
+                    direction,
+                    speed,
+                    program,
+                } => {
+                    let direction = match direction {
+                        Some((kind, value)) => {
+                            let value = value.eval(rank, rng);
+                            self.target_direction(*kind, value, context)
+                        }
+                        None => context.aim_angle(),
+                    };
+                    let speed = match speed {
+                        Some((kind, value)) => {
+                            let value = value.eval(rank, rng);
+                            self.target_speed(*kind, value)
+                        }
+                        None => DEFAULT_SPEED,
+                    };
+                    self.last_direction = direction;
+                    self.last_speed = speed;
+                    let mut bullet =
+                        pattern_bullet(context.origin, direction, speed, 0.0, DEFAULT_SIZE);
+                    if let Some(program) = program {
+                        bullet.set_pattern(PatternRunner::for_bullet(*program, direction, speed));
+                    }
+                    shots.push(bullet);
+                }
+                Op::Wait(time) => self.wait += time.eval(rank, rng),
+                Op::AngleSweep(delta) => self.sweep_angle += delta.to_radians(),
+                Op::SpeedSweep(delta) => self.sweep_speed += delta,
+                Op::RepeatStart { times, end } => {
+                    let times = times.eval(rank, rng).floor();
+                    if times >= 1.0 {
+                        self.loops[self.loop_depth as usize] = (self.pc, times as u32);
+                        self.loop_depth += 1;
+                    } else {
+                        self.pc = *end as u16;
+                    }
+                }
+                Op::RepeatEnd => {
+                    let (start, remaining) = &mut self.loops[self.loop_depth as usize - 1];
+                    *remaining -= 1;
+                    if *remaining > 0 {
+                        self.pc = *start;
+                    } else {
+                        self.loop_depth -= 1;
+                    }
+                }
+                Op::Call(program) => {
+                    self.calls[self.call_depth as usize] = (self.program, self.pc);
+                    self.call_depth += 1;
+                    self.program = *program as u16;
+                    self.pc = 0;
+                }
+                Op::ChangeDirection { kind, value, term } => {
+                    let value = value.eval(rank, rng);
+                    let term = term.eval(rank, rng).max(MIN_TERM);
+                    let rate = match kind {
+                        DirectionType::Sequence => value,
+                        DirectionType::Relative => value / term,
+                        _ => {
+                            let target = self.target_direction(*kind, value, context);
+                            normalize_angle(target - self.direction) / term
+                        }
+                    };
+                    self.turn = (rate, term);
+                }
+                Op::ChangeSpeed { kind, value, term } => {
+                    let value = value.eval(rank, rng);
+                    let term = term.eval(rank, rng).max(MIN_TERM);
+                    let rate = match kind {
+                        SpeedType::Sequence => value,
+                        SpeedType::Relative => value / term,
+                        SpeedType::Absolute => (value - self.speed) / term,
+                    };
+                    self.speed_change = (rate, term);
+                }
+                Op::Accel {
+                    horizontal,
+                    vertical,
+                    term,
+                } => {
+                    let term = term.eval(rank, rng).max(MIN_TERM);
+                    let mut rate = |change: &Option<(SpeedType, Expr)>, current: f32| match change {
+                        Some((SpeedType::Sequence, value)) => value.eval(rank, rng),
+                        Some((SpeedType::Relative, value)) => value.eval(rank, rng) / term,
+                        Some((SpeedType::Absolute, value)) => {
+                            (value.eval(rank, rng) - current) / term
+                        }
+                        None => 0.0,
+                    };
+                    let rate_x = rate(horizontal, self.accel_vel.0);
+                    let rate_y = rate(vertical, self.accel_vel.1);
+                    self.accel = (Vector2(rate_x, rate_y), term);
+                }
+                Op::Bounce(count) => self.bounces = *count,
+                Op::Vanish => self.vanished = true,
+            }
+        }
+    }
+
+    // 時間をかけて変える向き・速さ・加速度を進める
+    fn update_changes(&mut self, dt: f32) {
+        if self.turn.1 > 0.0 {
+            self.direction += self.turn.0 * dt.min(self.turn.1);
+            self.turn.1 -= dt;
+        }
+        if self.speed_change.1 > 0.0 {
+            self.speed += self.speed_change.0 * dt.min(self.speed_change.1);
+            self.speed_change.1 -= dt;
+        }
+        if self.accel.1 > 0.0 {
+            self.accel_vel += self.accel.0 * dt.min(self.accel.1);
+            self.accel.1 -= dt;
+        }
+    }
+
+    fn target_direction(&self, kind: DirectionType, value: f32, context: &PatternContext) -> f32 {
+        match kind {
+            DirectionType::Aim => context.aim_angle() + value,
+            DirectionType::Absolute => value,
+            DirectionType::Relative => self.direction + value,
+            DirectionType::Sequence => self.last_direction + value,
+        }
+    }
+
+    fn target_speed(&self, kind: SpeedType, value: f32) -> f32 {
+        match kind {
+            SpeedType::Absolute => value,
+            SpeedType::Relative => self.speed + value,
+            SpeedType::Sequence => self.last_speed + value,
+        }
+    }
+
+    fn fire(
+        &self,
+        command: &Command,
+        program: Option<usize>,
+        context: &PatternContext,
+        shots: &mut Vec<Actor>,
+    ) {
+        let origin = context.origin;
+        let sweep_speed = self.sweep_speed;
+        let rank = context.rank;
+        // program を持たせた弾は、パターンが向きと速さを決める
+        let bullet = |angle: f32, speed: f32, ang_vel: f32, size: f32| {
+            let mut bullet = pattern_bullet(origin, angle, speed, ang_vel, size);
+            if let Some(program) = program {
+                bullet.set_pattern(PatternRunner::for_bullet(program, angle, speed));
+            }
+            bullet
+        };
+        // aimed なら自機の方向、lead なら自機の移動先を基準にする
+        let direction = |angle: f32, aimed: bool, lead: Option<f32>| {
+            let angle = angle.to_radians() + self.sweep_angle;
+            match (aimed, lead) {
+                (true, Some(speed)) => context.lead_angle(speed) + angle,
+                (true, None) => context.aim_angle() + angle,
+                (false, _) => angle,
+            }
+        };
+        let lead_speed = |lead: bool, speed: f32| {
+            if lead {
+                Some(speed + sweep_speed)
+            } else {
+                None
+            }
+        };
+        match *command {
+            Command::Ring {
+                count,
+                speed,
+                angle,
+                ang_vel,
+                size,
+                ..
+            } => {
+                let (count, speed) = (rank_count(count, rank), rank_speed(speed, rank));
+                let base = direction(angle, false, None);
+                let step = 2.0 * std::f32::consts::PI / count as f32;
+                shots.extend(
+                    (0..count).map(|i| {
+                        bullet(base + step * i as f32, speed + sweep_speed, ang_vel, size)
+                    }),
+                );
+            }
+            Command::Fan {
+                count,
+                spread,
+                speed,
+                angle,
+                aimed,
+                lead,
+                ang_vel,
+                size,
+                ..
+            } => {
+                let (count, speed) = (rank_count(count, rank), rank_speed(speed, rank));
+                let center = direction(angle, aimed, lead_speed(lead, speed));
+                let angles = fan_angles(center, spread.to_radians(), count);
+                shots.extend(angles.map(|a| bullet(a, speed + sweep_speed, ang_vel, size)));
+            }
+            Command::Aimed {
+                speed,
+                angle,
+                lead,
+                size,
+                ..
+            } => {
+                let speed = rank_speed(speed, rank);
+                shots.push(bullet(
+                    direction(angle, true, lead_speed(lead, speed)),
+                    speed + sweep_speed,
+                    0.0,
+                    size,
+                ))
+            }
+            Command::Homing {
+                speed,
+                turn_rate,
+                count,
+                spread,
+                duration,
+                angle,
+                aimed,
+                size,
+            } => {
+                let (count, speed) = (rank_count(count, rank), rank_speed(speed, rank));
+                let angles = fan_angles(direction(angle, aimed, None), spread.to_radians(), count);
+                shots.extend(angles.map(|a| {
+                    create_homing_bullet(
+                        origin + vec_from_angle(a),
+                        a,
+                        speed + sweep_speed,
+                        turn_rate.to_radians(),
+                        duration,
+                        size,
+                    )
+                }));
+            }
+            Command::Laser {
+                length,
+                width,
+                warn_time,
+                duration,
+                angle,
+                aimed,
+                ang_vel,
+            } => shots.push(create_laser(
+                origin,
+                direction(angle, aimed, None),
+                length,
+                width,
+                warn_time,
+                duration,
+                ang_vel,
+            )),
+            Command::Beams {
+                count,
+                length,
+                width,
+                warn_time,
+                duration,
+                ang_vel,
+            } => shots.extend(create_rotating_beams(
+                origin,
+                count as i32,
+                length,
+                width,
+                warn_time,
+                duration,
+                ang_vel,
+            )),
+            Command::Snake {
+                speed,
+                segments,
+                interval,
+                width,
+                angle,
+                aimed,
+                ang_vel,
+            } => shots.extend(create_snake_laser(
+                origin,
+                direction(angle, aimed, None),
+                rank_speed(speed, rank) + sweep_speed,
+                ang_vel,
+                segments as i32,
+                interval,
+                width,
+            )),
+            _ => (),
+        }
+    }
+}
+
+// from から to を向く方向
+pub fn aim_angle(from: Vector2, to: Vector2) -> f32 {
+    (to - from).angle()
+}
+
+// 等速で動く相手に、速さ speed の弾を当てる方向
+// |d + v t| = speed t を満たす最小の t > 0 を求める。追いつけない場合は今の位置を狙う
+pub fn lead_angle(from: Vector2, to: Vector2, to_vel: Vector2, speed: f32) -> f32 {
+    let d = to - from;
+    let a = to_vel.norm_squared() - speed * speed;
+    let b = 2.0 * d.dot(to_vel);
+    let c = d.norm_squared();
+    let t = if a.abs() < std::f32::EPSILON {
+        // 弾と相手の速さが同じ場合は 1 次方程式になる
+        if b < 0.0 {
+            -c / b
+        } else {
+            -1.0
+        }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            -1.0
+        } else {
+            let sqrt = discriminant.sqrt();
+            let (t1, t2) = ((-b - sqrt) / (2.0 * a), (-b + sqrt) / (2.0 * a));
+            match (t1 > 0.0, t2 > 0.0) {
+                (true, true) => t1.min(t2),
+                (true, false) => t1,
+                (false, true) => t2,
+                (false, false) => -1.0,
+            }
+        }
+    };
+    if t > 0.0 {
+        aim_angle(from, to + to_vel * t)
+    } else {
+        aim_angle(from, to)
+    }
+}
+
+// center を中心に spread の範囲へ count 個の方向を等間隔に並べる
+pub fn fan_angles(center: f32, spread: f32, count: u32) -> impl Iterator<Item = f32> {
+    let step = if count > 1 {
+        spread / (count - 1) as f32
+    } else {
+        0.0
+    };
+    let start = if count > 1 {
+        center - spread / 2.0
+    } else {
+        center
+    };
+    (0..count).map(move |i| start + step * i as f32)
+}
+
+fn pattern_bullet(x_y: Vector2, angle: f32, vel_norm: f32, ang_vel: f32, size: f32) -> Actor {
+    create_bullet(
+        x_y + vec_from_angle(angle),
+        Vector2(size, size),
+        angle,
+        vec_from_angle(angle) * vel_norm,
+        ang_vel,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn book(text: &str) -> PatternBook {
+        PatternBook::from_toml(text).unwrap()
+    }
+
+    // 原点から撃ち、自機は真下 (0, -100) に止まっている
+    fn context() -> PatternContext {
+        PatternContext {
+            origin: Vector2(0.0, 0.0),
+            target: Vector2(0.0, -100.0),
+            target_vel: Vector2(0.0, 0.0),
+            rank: 0.5,
+        }
+    }
+
+    // 弾のパターンを seconds 秒だけ進め、撃たれた弾を返す
+    // 命令は変化を進めた後に実行するので、accel や turn の変化は次のステップから始まる
+    fn run(runner: &mut PatternRunner, book: &PatternBook, seconds: f32) -> Vec<Actor> {
+        let mut rng = Rand32::new(0);
+        let mut shots = Vec::new();
+        for _ in 0..(seconds / DT).round() as u32 {
+            runner.update(book, DT, &context(), &mut rng, &mut shots);
+        }
+        shots
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn accel_changes_speed_over_time_and_stops() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "brake"
+            commands = [{ type = "accel", speed = 0.0, time = 0.5 }]
+            "#,
+        );
+        let mut runner = PatternRunner::for_bullet(book.find("brake").unwrap(), 0.0, 120.0);
+        run(&mut runner, &book, DT);
+        run(&mut runner, &book, 0.25);
+        assert_near(runner.velocity().norm(), 60.0);
+        run(&mut runner, &book, 0.5);
+        assert_near(runner.velocity().norm(), 0.0);
+    }
+
+    #[test]
+    fn turn_aims_at_the_target_or_turns_relative() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "aim"
+            commands = [{ type = "turn", angle = 0.0, aimed = true }]
+
+            [[pattern]]
+            name = "relative"
+            commands = [{ type = "turn", angle = 30.0, time = 1.0, relative = true }]
+            "#,
+        );
+        // 右へ進む弾が真下の自機へ向き直る
+        let right = std::f32::consts::FRAC_PI_2;
+        let mut runner = PatternRunner::for_bullet(book.find("aim").unwrap(), right, 100.0);
+        run(&mut runner, &book, 2.0 * DT);
+        let vel = runner.velocity();
+        assert_near(vel.0, 0.0);
+        assert_near(vel.1, -100.0);
+
+        // 1 秒かけて 30 度曲がるので、半分の時間では 15 度
+        let mut runner = PatternRunner::for_bullet(book.find("relative").unwrap(), 0.0, 100.0);
+        run(&mut runner, &book, DT);
+        run(&mut runner, &book, 0.5);
+        assert_near(runner.velocity().angle(), 15f32.to_radians());
+        run(&mut runner, &book, 1.0);
+        assert_near(runner.velocity().angle(), 30f32.to_radians());
+    }
+
+    #[test]
+    fn split_fires_from_the_bullet_and_vanishes() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "split"
+            commands = [
+                { type = "wait", time = 0.5 },
+                { type = "ring", count = 8, speed = 60.0 },
+                { type = "vanish" },
+            ]
+            "#,
+        );
+        let mut runner = PatternRunner::for_bullet(book.find("split").unwrap(), 0.0, 100.0);
+        assert!(run(&mut runner, &book, 0.25).is_empty());
+        assert!(!runner.is_vanished());
+        let shots = run(&mut runner, &book, 0.5);
+        assert_eq!(shots.len(), 8);
+        assert!(runner.is_vanished());
+        // 消えた後は何も撃たない
+        assert!(run(&mut runner, &book, 1.0).is_empty());
+    }
+
+    #[test]
+    fn script_is_attached_to_fired_bullets() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "brake"
+            commands = [{ type = "accel", speed = 0.0, time = 0.5 }]
+
+            [[pattern]]
+            name = "ring"
+            commands = [
+                { type = "ring", count = 4, speed = 100.0, script = "brake" },
+                { type = "wait", time = 1.0 },
+            ]
+            "#,
+        );
+        let mut runner = PatternRunner::new(book.find("ring").unwrap());
+        let shots = run(&mut runner, &book, DT);
+        assert_eq!(shots.len(), 4);
+        for shot in &shots {
+            let pattern = shot.get_pattern().unwrap();
+            assert_near(pattern.velocity().norm(), 100.0);
+        }
+    }
+
+    #[test]
+    fn bounce_reflects_until_the_count_runs_out() {
+        let book = book(
+            r#"
+            [[pattern]]
+            name = "bounce"
+            commands = [{ type = "bounce", count = 2 }]
+            "#,
+        );
+        // 右上へ進む弾
+        let angle = std::f32::consts::FRAC_PI_4;
+        let mut runner = PatternRunner::for_bullet(book.find("bounce").unwrap(), angle, 100.0);
+        run(&mut runner, &book, DT);
+        assert_eq!(runner.get_bounces(), 2);
+        let before = runner.velocity();
+
+        // 左右の壁では x だけ、上下の壁では y だけ反転する
+        runner.reflect(true, false);
+        assert_near(runner.velocity().0, -before.0);
+        assert_near(runner.velocity().1, before.1);
+        runner.reflect(false, true);
+        assert_near(runner.velocity().0, -before.0);
+        assert_near(runner.velocity().1, -before.1);
+        assert_eq!(runner.get_bounces(), 0);
+        runner.reflect(true, false);
+        assert_eq!(runner.get_bounces(), 0);
+    }
+}
@@ -0,0 +1,178 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// 最後のフレームまで進んだ後の動き
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimMode {
+    // 最初のフレームに戻る
+    Loop,
+    // 逆順に戻り、また進む
+    PingPong,
+    // 最後のフレームで止まる
+    Once,
+}
+
+impl Default for AnimMode {
+    fn default() -> Self {
+        Self::Loop
+    }
+}
+
+// フレームを順に切り替えるアニメーション。data/animations.toml から読み込む
+// 経過時間だけでフレームが決まるので、固定ステップで時間を進めれば毎回同じ絵になる
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Animation {
+    // Assets のスプライトの名前
+    frames: Vec<String>,
+    // 1 フレームを表示する時間 [s]
+    frame_time: f32,
+    #[serde(default)]
+    mode: AnimMode,
+}
+
+impl Animation {
+    // 1 周にかかる時間 [s]。Once なら最後のフレームが終わるまでの時間
+    pub fn duration(&self) -> f32 {
+        self.frame_time * self.frames.len() as f32
+    }
+
+    // 再生を始めてから time 秒後に表示するフレームの名前
+    pub fn frame_at(&self, time: f32) -> &str {
+        let n = self.frames.len();
+        let step = (time.max(0.0) / self.frame_time) as usize;
+        let i = match self.mode {
+            AnimMode::Loop => step % n,
+            AnimMode::PingPong if n > 1 => {
+                let period = 2 * (n - 1);
+                let j = step % period;
+                if j < n {
+                    j
+                } else {
+                    period - j
+                }
+            }
+            AnimMode::PingPong => 0,
+            AnimMode::Once => step.min(n - 1),
+        };
+        &self.frames[i]
+    }
+
+    // Once のアニメーションが最後のフレームまで表示し終えたか
+    pub fn is_finished(&self, time: f32) -> bool {
+        self.mode == AnimMode::Once && time >= self.duration()
+    }
+
+    pub fn get_frames(&self) -> &[String] {
+        &self.frames
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct AnimationDef {
+    name: String,
+    #[serde(flatten)]
+    animation: Animation,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct AnimationFile {
+    animation: Vec<AnimationDef>,
+}
+
+#[derive(Debug, Default)]
+pub struct AnimationBook {
+    animations: HashMap<String, Animation>,
+}
+
+impl AnimationBook {
+    pub fn new() -> Self {
+        AnimationBook::default()
+    }
+
+    pub fn load(path: &str) -> Result<AnimationBook, Box<dyn Error>> {
+        let file: AnimationFile = toml::from_str(&fs::read_to_string(path)?)?;
+        let mut book = AnimationBook::new();
+        for def in file.animation {
+            if def.animation.frames.is_empty() || def.animation.frame_time <= 0.0 {
+                return Err(format!(
+                    "animation {} needs frames and a positive frame_time",
+                    def.name
+                )
+                .into());
+            }
+            if book
+                .animations
+                .insert(def.name.clone(), def.animation)
+                .is_some()
+            {
+                return Err(format!("duplicate animation: {}", def.name).into());
+            }
+        }
+        Ok(book)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Animation> {
+        self.animations.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Animation)> {
+        self.animations.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(frames: usize, mode: AnimMode) -> Animation {
+        Animation {
+            frames: (0..frames).map(|i| i.to_string()).collect(),
+            frame_time: 0.25,
+            mode: mode,
+        }
+    }
+
+    // 各フレームの途中の時刻で見たフレームの並び
+    fn sequence(animation: &Animation, steps: usize) -> Vec<&str> {
+        (0..steps)
+            .map(|i| animation.frame_at((i as f32 + 0.5) * 0.25))
+            .collect()
+    }
+
+    #[test]
+    fn loop_starts_over() {
+        let anim = animation(3, AnimMode::Loop);
+        assert_eq!(sequence(&anim, 7), ["0", "1", "2", "0", "1", "2", "0"]);
+        assert!(!anim.is_finished(100.0));
+    }
+
+    #[test]
+    fn ping_pong_turns_back_without_repeating_the_ends() {
+        let anim = animation(3, AnimMode::PingPong);
+        assert_eq!(
+            sequence(&anim, 9),
+            ["0", "1", "2", "1", "0", "1", "2", "1", "0"]
+        );
+        // 1 フレームしかなければそのまま
+        let single = animation(1, AnimMode::PingPong);
+        assert_eq!(sequence(&single, 3), ["0", "0", "0"]);
+    }
+
+    #[test]
+    fn once_holds_the_last_frame() {
+        let anim = animation(3, AnimMode::Once);
+        assert_eq!(sequence(&anim, 5), ["0", "1", "2", "2", "2"]);
+        assert!(!anim.is_finished(0.7));
+        assert!(anim.is_finished(0.75));
+    }
+
+    #[test]
+    fn negative_time_shows_the_first_frame() {
+        for &mode in &[AnimMode::Loop, AnimMode::PingPong, AnimMode::Once] {
+            assert_eq!(animation(3, mode).frame_at(-1.0), "0");
+        }
+    }
+}
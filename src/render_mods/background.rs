@@ -0,0 +1,108 @@
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+
+use serde::Deserialize;
+
+use crate::render_mods::layout::{PLAYFIELD_POS, PLAYFIELD_W_H};
+use crate::vector2::Vector2;
+
+fn default_tint() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+// data/stage.toml の [[background]]。上に書いたものほど奥に描く
+#[derive(Clone, Debug, Deserialize)]
+pub struct LayerDef {
+    // ggez の resources からのパス。プレイフィールドいっぱいに敷き詰める
+    pub image: String,
+    // スクロールの速度 [px/s]。y が負だと画面の下へ流れる。奥の層ほど遅くすると奥行きが出る
+    #[serde(default)]
+    pub scroll: [f32; 2],
+    // 画像に掛ける色 (r, g, b, a)
+    #[serde(default = "default_tint")]
+    pub tint: [f32; 4],
+    // ステージ開始から現れるまでの時間 [s]
+    #[serde(default)]
+    pub time: f32,
+    // 現れてから不透明になるまでの時間 [s]
+    #[serde(default)]
+    pub fade: f32,
+}
+
+// 背景の 1 層。読み込んだ定義と画像に、今のスクロールのずれを添える
+#[derive(Debug)]
+struct Layer {
+    def: LayerDef,
+    image: graphics::Image,
+    // 画像の大きさを 1 としたスクロールのずれ。毎ステップ 1 枚分で折り返す
+    offset: Vector2,
+}
+
+// ステージの背景。update でスクロールを進め、ステージの経過時間を渡して描く
+#[derive(Debug, Default)]
+pub struct Background {
+    layers: Vec<Layer>,
+}
+
+impl Background {
+    pub fn new() -> Self {
+        Background::default()
+    }
+
+    pub fn load(ctx: &mut Context, defs: &[LayerDef]) -> GameResult<Background> {
+        let mut layers = Vec::new();
+        for def in defs {
+            let mut image = graphics::Image::new(ctx, &def.image)?;
+            image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
+            layers.push(Layer {
+                def: def.clone(),
+                image: image,
+                offset: Vector2(0.0, 0.0),
+            });
+        }
+        Ok(Background { layers: layers })
+    }
+
+    // 現れている層のスクロールを dt だけ進める
+    // 経過時間に速度を掛けると長く流したときに f32 の精度が落ちるので、ずれを折り返しながら足していく
+    pub fn update(&mut self, dt: f32, stage_time: f32) {
+        for layer in &mut self.layers {
+            if stage_time < layer.def.time {
+                continue;
+            }
+            let (w, h) = (
+                f32::from(layer.image.width()),
+                f32::from(layer.image.height()),
+            );
+            let step = Vector2(-layer.def.scroll[0] / w, layer.def.scroll[1] / h) * dt;
+            let offset = layer.offset + step;
+            layer.offset = Vector2(offset.0.rem_euclid(1.0), offset.1.rem_euclid(1.0));
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, stage_time: f32) -> GameResult {
+        for Layer { def, image, offset } in &self.layers {
+            let time = stage_time - def.time;
+            if time < 0.0 {
+                continue;
+            }
+            let alpha = if def.fade > 0.0 {
+                (time / def.fade).min(1.0)
+            } else {
+                1.0
+            };
+            // 画像の範囲を 1 より大きくとると、その分だけ繰り返して描かれる
+            let (w, h) = (f32::from(image.width()), f32::from(image.height()));
+            let src =
+                graphics::Rect::new(offset.0, offset.1, PLAYFIELD_W_H.0 / w, PLAYFIELD_W_H.1 / h);
+            let [r, g, b, a] = def.tint;
+            let param = graphics::DrawParam::new()
+                .src(src)
+                .dest(na::Point2::new(PLAYFIELD_POS.0, PLAYFIELD_POS.1))
+                .color(graphics::Color::new(r, g, b, a * alpha));
+            graphics::draw(ctx, image, param)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use ggez::graphics;
+
+use oorandom::Rand32;
+
+use crate::render_mods::layout::{PLAYFIELD_POS, PLAYFIELD_W_H};
+use crate::vector2::Vector2;
+
+// 揺れの向きを決める乱数。ゲームの乱数とは別の系列にする
+const SHAKE_SEED: u64 = 0x5ca1e;
+// トラウマが 1 のときの揺れの幅 [px]
+const MAX_SHAKE: f32 = 12.0;
+// 1 秒あたりに減るトラウマ
+const TRAUMA_DECAY: f32 = 1.2;
+// 一時的なズームが 1 秒あたりに戻る割合
+const ZOOM_PUNCH_DECAY: f32 = 4.0;
+
+// プレイフィールドを映すカメラ。位置と倍率、揺れを持つ
+// ワールド座標から論理画面への変換 (world_to_screen_coords) はそのままに、
+// graphics::set_screen_coordinates に渡す範囲を view で動かすので、すべての描画に効く
+// 揺れはトラウマ [0, 1] で表し、その 2 乗に比例させる。大きな衝撃ほど強く揺れ、すぐ収まる
+#[derive(Clone, Debug)]
+pub struct Camera {
+    // 画面の中心に映すワールド座標
+    offset: Vector2,
+    // 1 より大きいと拡大する
+    zoom: f32,
+    // 演出で一時的に足す倍率。時間とともに 0 に戻る
+    zoom_punch: f32,
+    trauma: f32,
+    shake_offset: Vector2,
+    // 揺れを止めるアクセシビリティの設定
+    shake_enabled: bool,
+    rng: Rand32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            offset: Vector2(0.0, 0.0),
+            zoom: 1.0,
+            zoom_punch: 0.0,
+            trauma: 0.0,
+            shake_offset: Vector2(0.0, 0.0),
+            shake_enabled: true,
+            rng: Rand32::new(SHAKE_SEED),
+        }
+    }
+
+    pub fn with_shake(mut self, enabled: bool) -> Self {
+        self.shake_enabled = enabled;
+        self
+    }
+
+    pub fn get_offset(&self) -> Vector2 {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: Vector2) {
+        self.offset = offset;
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(0.1);
+    }
+
+    // 揺れを足す。トラウマは 1 を超えない
+    pub fn add_trauma(&mut self, amount: f32) {
+        if self.shake_enabled {
+            self.trauma = (self.trauma + amount).min(1.0);
+        }
+    }
+
+    // 一時的に amount だけ拡大する。すでに拡大していれば大きい方を残す
+    pub fn punch_zoom(&mut self, amount: f32) {
+        self.zoom_punch = self.zoom_punch.max(amount);
+    }
+
+    // 揺れとズームを固定ステップで進める
+    pub fn update(&mut self, dt: f32) {
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        self.shake_offset = if self.trauma > 0.0 {
+            let strength = MAX_SHAKE * self.trauma * self.trauma;
+            let rng = &mut self.rng;
+            let mut random = || rng.rand_float() * 2.0 - 1.0;
+            Vector2(random(), random()) * strength
+        } else {
+            Vector2(0.0, 0.0)
+        };
+        self.zoom_punch *= (1.0 - ZOOM_PUNCH_DECAY * dt).max(0.0);
+    }
+
+    // カメラを通して見たときに、論理画面の rect の位置に映る範囲
+    // graphics::set_screen_coordinates に渡す
+    pub fn view(&self, rect: graphics::Rect) -> graphics::Rect {
+        let zoom = self.zoom + self.zoom_punch;
+        // 拡大の中心はプレイフィールドの中心
+        let center = PLAYFIELD_POS + PLAYFIELD_W_H / 2.0;
+        let shift = Vector2(self.offset.0, -self.offset.1) + self.shake_offset;
+        graphics::Rect::new(
+            center.0 + (rect.x - center.0) / zoom + shift.0,
+            center.1 + (rect.y - center.1) / zoom + shift.1,
+            rect.w / zoom,
+            rect.h / zoom,
+        )
+    }
+}
@@ -0,0 +1,49 @@
+use crate::actor_mods::actor::ActorType;
+
+// 描画の層。下に書いたものほど手前に描く
+// 敵弾は自機より手前に、当たり判定などのデバッグ表示は演出より手前に出る
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    Background,
+    Enemies,
+    Items,
+    Player,
+    PlayerShots,
+    EnemyShots,
+    Effects,
+    Hud,
+    Debug,
+}
+
+impl Layer {
+    // 奥から手前への順
+    pub const ALL: [Layer; 9] = [
+        Layer::Background,
+        Layer::Enemies,
+        Layer::Items,
+        Layer::Player,
+        Layer::PlayerShots,
+        Layer::EnemyShots,
+        Layer::Effects,
+        Layer::Hud,
+        Layer::Debug,
+    ];
+
+    // 機体そのものを描く層
+    pub fn of_body(tag: ActorType) -> Layer {
+        match tag {
+            ActorType::Player => Layer::Player,
+            ActorType::Item => Layer::Items,
+            ActorType::Effect => Layer::Effects,
+            _ => Layer::Enemies,
+        }
+    }
+
+    // tag の機体が撃った弾を描く層
+    pub fn of_shots(tag: ActorType) -> Layer {
+        match tag {
+            ActorType::Player => Layer::PlayerShots,
+            _ => Layer::EnemyShots,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use ggez::graphics;
+use ggez::{Context, GameResult};
+
+use crate::vector2::Vector2;
+
+// 論理画面の大きさ。ウィンドウの大きさや hidpi によらず、この座標系で描いて拡大・縮小する
+pub const SCREEN_W_H: Vector2 = Vector2(640.0, 480.0);
+// プレイフィールドの大きさ。ワールド座標はこの中心を原点とし、当たり判定や画面外の判定もこの範囲で行う
+pub const PLAYFIELD_W_H: Vector2 = Vector2(384.0, 448.0);
+// 論理画面でのプレイフィールドの左上
+pub const PLAYFIELD_POS: Vector2 = Vector2(32.0, 16.0);
+// 論理画面での右側の HUD の左上
+pub const HUD_POS: Vector2 = Vector2(448.0, 16.0);
+
+const FRAME_COLOR: graphics::Color = graphics::Color::new(0.15, 0.1, 0.25, 1.0);
+// 黒帯はウィンドウがどれだけ細長くても覆えるよう十分に大きくとる
+const LETTERBOX_SIZE: f32 = 100_000.0;
+
+// world_to_screen_coords に渡す値。ワールドの原点がプレイフィールドの中心に描かれる
+pub fn world_coords() -> (f32, f32) {
+    (
+        (PLAYFIELD_POS.0 + PLAYFIELD_W_H.0 / 2.0) * 2.0,
+        (PLAYFIELD_POS.1 + PLAYFIELD_W_H.1 / 2.0) * 2.0,
+    )
+}
+
+// 大きさ window_w_h のウィンドウの中央に、縦横比を保って論理画面を最大に映す範囲
+// graphics::set_screen_coordinates に渡す。余った部分は黒帯になる
+pub fn letterbox(window_w_h: Vector2) -> graphics::Rect {
+    let scale = (window_w_h.0 / SCREEN_W_H.0).min(window_w_h.1 / SCREEN_W_H.1);
+    let view_w_h = window_w_h / scale;
+    graphics::Rect::new(
+        (SCREEN_W_H.0 - view_w_h.0) / 2.0,
+        (SCREEN_W_H.1 - view_w_h.1) / 2.0,
+        view_w_h.0,
+        view_w_h.1,
+    )
+}
+
+// プレイフィールドの周りの枠と、論理画面の外の黒帯
+// プレイフィールドからはみ出して描かれた弾はこれで隠れるので、ワールドを描いた後に描く
+pub fn build_frame(ctx: &mut Context) -> GameResult<graphics::Mesh> {
+    let (sw, sh) = (SCREEN_W_H.0, SCREEN_W_H.1);
+    let (fx, fy) = (PLAYFIELD_POS.0, PLAYFIELD_POS.1);
+    let (fw, fh) = (PLAYFIELD_W_H.0, PLAYFIELD_W_H.1);
+    let l = LETTERBOX_SIZE;
+    let frame = [
+        graphics::Rect::new(0.0, 0.0, sw, fy),
+        graphics::Rect::new(0.0, fy + fh, sw, sh - fy - fh),
+        graphics::Rect::new(0.0, fy, fx, fh),
+        graphics::Rect::new(fx + fw, fy, sw - fx - fw, fh),
+    ];
+    let letterbox = [
+        graphics::Rect::new(-l, -l, sw + l * 2.0, l),
+        graphics::Rect::new(-l, sh, sw + l * 2.0, l),
+        graphics::Rect::new(-l, 0.0, l, sh),
+        graphics::Rect::new(sw, 0.0, l, sh),
+    ];
+    let mut builder = graphics::MeshBuilder::new();
+    for &rect in &frame {
+        builder.rectangle(graphics::DrawMode::fill(), rect, FRAME_COLOR);
+    }
+    for &rect in &letterbox {
+        builder.rectangle(graphics::DrawMode::fill(), rect, graphics::BLACK);
+    }
+    builder.build(ctx)
+}
@@ -0,0 +1,9 @@
+pub mod animation;
+pub mod background;
+pub mod camera;
+pub mod layer;
+pub mod layout;
+pub mod particles;
+pub mod post_process;
+pub mod sprite_batch;
+pub mod sprite_sheet;
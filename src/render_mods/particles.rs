@@ -0,0 +1,243 @@
+use ggez::graphics;
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::Drawable;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+
+use oorandom::Rand32;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+use crate::assets::Assets;
+use crate::vector2::{random_vec, vec_from_angle, Vector2};
+
+// 一度に存在できる粒子の上限。超えた分は出さない
+const MAX_PARTICLES: usize = 4096;
+// ゲームの乱数とは別の系列を使い、演出が弾幕の乱数をずらさないようにする
+const PARTICLE_SEED: u64 = 0x5eed;
+
+fn default_sprite() -> String {
+    "particle".to_string()
+}
+
+fn default_spread() -> f32 {
+    360.0
+}
+
+// 粒子の出し方。data/particles.toml から読み込む
+// 範囲を表す [a, b] は、粒子ごとに a から b までの値を乱数で選ぶ
+// 寿命で変わる [始め, 終わり] は、年齢に応じて線形に補間する
+#[derive(Clone, Debug, Deserialize)]
+struct EmitterDef {
+    name: String,
+    #[serde(default = "default_sprite")]
+    sprite: String,
+    // burst で一度に出す数
+    #[serde(default)]
+    count: u32,
+    // emit で 1 秒あたりに出す数
+    #[serde(default)]
+    rate: f32,
+    // 速さの範囲 [px/s]
+    speed: [f32; 2],
+    // 放出する方向と広がり [度]。0 が画面上方向
+    #[serde(default)]
+    angle: f32,
+    #[serde(default = "default_spread")]
+    spread: f32,
+    // 出現位置のばらつき [px]
+    #[serde(default)]
+    radius: f32,
+    // 寿命の範囲 [s]
+    life: [f32; 2],
+    // 1 秒あたりに失う速さの割合
+    #[serde(default)]
+    drag: f32,
+    // [始め, 終わり] の大きさ [px]
+    size: [f32; 2],
+    // [始め, 終わり] の色 (r, g, b, a)
+    color: [[f32; 4]; 2],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ParticleFile {
+    emitter: Vec<EmitterDef>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    def: usize,
+    x_y: Vector2,
+    vel: Vector2,
+    age: f32,
+    life: f32,
+}
+
+// 連続して粒子を出す放出源。持ち主が位置を渡して毎ステップ emit する
+#[derive(Copy, Clone, Debug)]
+pub struct Emitter {
+    def: usize,
+    // 次のステップへ持ち越す端数
+    pending: f32,
+}
+
+// 爆発や火花などの粒子。固定ステップで動かし、加算合成でまとめて描く
+pub struct ParticleSystem {
+    defs: Vec<EmitterDef>,
+    particles: Vec<Particle>,
+    // 画像のパスごとの SpriteBatch
+    batches: Vec<(String, SpriteBatch)>,
+    rng: Rand32,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        ParticleSystem {
+            defs: Vec::new(),
+            particles: Vec::new(),
+            batches: Vec::new(),
+            rng: Rand32::new(PARTICLE_SEED),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<ParticleSystem, Box<dyn Error>> {
+        let file: ParticleFile = toml::from_str(&fs::read_to_string(path)?)?;
+        for (i, def) in file.emitter.iter().enumerate() {
+            if file.emitter[..i].iter().any(|d| d.name == def.name) {
+                return Err(format!("duplicate emitter: {}", def.name).into());
+            }
+            if def.life[0] <= 0.0 || def.life[1] < def.life[0] {
+                return Err(format!("emitter {}: life must be positive", def.name).into());
+            }
+        }
+        let mut system = ParticleSystem::new();
+        system.defs = file.emitter;
+        Ok(system)
+    }
+
+    // Assets に sprite があるかを確かめる
+    pub fn validate(&self, has_sprite: impl Fn(&str) -> bool) -> Result<(), String> {
+        for def in &self.defs {
+            if !has_sprite(&def.sprite) {
+                return Err(format!(
+                    "emitter {}: unknown sprite: {}",
+                    def.name, def.sprite
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.defs.iter().position(|def| def.name == name)
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    // name の粒子を x_y から count 個まとめて出す。name がなければ何もしない
+    pub fn burst(&mut self, name: &str, x_y: Vector2) {
+        if let Some(def) = self.find(name) {
+            for _ in 0..self.defs[def].count {
+                self.spawn(def, x_y);
+            }
+        }
+    }
+
+    pub fn emitter(&self, name: &str) -> Option<Emitter> {
+        self.find(name).map(|def| Emitter {
+            def: def,
+            pending: 0.0,
+        })
+    }
+
+    // emitter から dt 秒分の粒子を x_y に出す
+    pub fn emit(&mut self, emitter: &mut Emitter, x_y: Vector2, dt: f32) {
+        emitter.pending += self.defs[emitter.def].rate * dt;
+        while emitter.pending >= 1.0 {
+            emitter.pending -= 1.0;
+            self.spawn(emitter.def, x_y);
+        }
+    }
+
+    fn spawn(&mut self, def: usize, x_y: Vector2) {
+        if self.particles.len() >= MAX_PARTICLES {
+            return;
+        }
+        let d = &self.defs[def];
+        let rng = &mut self.rng;
+        let mut range = |r: [f32; 2]| r[0] + (r[1] - r[0]) * rng.rand_float();
+        let angle = (d.angle + d.spread * (range([-0.5, 0.5]))).to_radians();
+        let speed = range(d.speed);
+        let life = range(d.life);
+        let offset = random_vec(&mut self.rng, d.radius);
+        self.particles.push(Particle {
+            def: def,
+            x_y: x_y + offset,
+            vel: vec_from_angle(angle) * speed,
+            age: 0.0,
+            life: life,
+        });
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let defs = &self.defs;
+        for p in &mut self.particles {
+            let drag = (1.0 - defs[p.def].drag * dt).max(0.0);
+            p.vel = p.vel * drag;
+            p.x_y = p.x_y + p.vel * dt;
+            p.age += dt;
+        }
+        self.particles.retain(|p| p.age < p.life);
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, assets: &Assets, coords: (f32, f32)) -> GameResult {
+        for p in &self.particles {
+            let def = &self.defs[p.def];
+            let sprite = match assets.get_sprite(&def.sprite, p.age) {
+                Some(sprite) => sprite,
+                None => continue,
+            };
+            let t = p.age / p.life;
+            let size = lerp(def.size[0], def.size[1], t);
+            let [c0, c1] = def.color;
+            let color = graphics::Color::new(
+                lerp(c0[0], c1[0], t),
+                lerp(c0[1], c1[1], t),
+                lerp(c0[2], c1[2], t),
+                lerp(c0[3], c1[3], t),
+            );
+            let w_h = sprite.get_w_h();
+            let x_y = p.x_y.world_to_screen_coords(coords);
+            let param = graphics::DrawParam::new()
+                .src(sprite.get_src())
+                .dest(na::Point2::new(x_y.0, x_y.1))
+                .offset(na::Point2::new(0.5, 0.5))
+                .scale(na::Vector2::new(size / w_h.0, size / w_h.1))
+                .color(color);
+
+            let sheet = sprite.get_sheet();
+            let batch = match self.batches.iter().position(|(s, _)| s == sheet) {
+                Some(i) => &mut self.batches[i].1,
+                None => {
+                    let mut batch = SpriteBatch::new(sprite.get_image().clone());
+                    batch.set_blend_mode(Some(graphics::BlendMode::Add));
+                    self.batches.push((sheet.to_string(), batch));
+                    &mut self.batches.last_mut().unwrap().1
+                }
+            };
+            batch.add(param);
+        }
+        for (_, batch) in &mut self.batches {
+            graphics::draw(ctx, batch, graphics::DrawParam::default())?;
+            batch.clear();
+        }
+        Ok(())
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -0,0 +1,219 @@
+use ggez::conf::NumSamples;
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+use crate::render_mods::camera::Camera;
+use crate::render_mods::layout::{self, PLAYFIELD_POS, PLAYFIELD_W_H, SCREEN_W_H};
+use crate::vector2::Vector2;
+
+const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.2, 0.3, 1.0];
+const SCANLINE_COLOR: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.25);
+
+fn default_true() -> bool {
+    true
+}
+
+// config/config.toml の画面の設定。書かなかった項目は既定値になる
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct VideoConfig {
+    // プレイフィールドにブラウン管風の走査線を重ねるか
+    #[serde(default)]
+    pub scanlines: bool,
+    // 被弾やボムで画面を揺らすか。揺れが苦手なら false にする
+    #[serde(default = "default_true")]
+    pub screen_shake: bool,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            scanlines: false,
+            screen_shake: true,
+        }
+    }
+}
+
+impl VideoConfig {
+    pub fn load(path: &str) -> Result<VideoConfig, Box<dyn Error>> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+// プレイフィールドを一度オフスクリーンの Canvas に描き、閃光や走査線をかけて画面に重ねる
+// begin と end の間に描いたものが Canvas に入る。HUD や imgui は end の後に画面へ直接描く
+pub struct PostProcess {
+    canvas: graphics::Canvas,
+    // 論理画面の 1px あたりの Canvas の画素数。ウィンドウが大きいほど細かく描く
+    resolution: f32,
+    // 閃光とフェードに使う 1x1 の白い四角
+    cover: graphics::Mesh,
+    scanlines: Option<graphics::Mesh>,
+    // 閃光の不透明度。時間とともに薄れる
+    flash: f32,
+    // 画面を覆う黒の不透明度と、1 秒あたりの変化量
+    fade: f32,
+    fade_speed: f32,
+}
+
+impl PostProcess {
+    pub fn new(ctx: &mut Context, config: VideoConfig) -> GameResult<PostProcess> {
+        let (w, h) = graphics::drawable_size(ctx);
+        let resolution = resolution(Vector2(w, h));
+        let cover = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, 1.0, 1.0),
+            graphics::WHITE,
+        )?;
+        let scanlines = if config.scanlines {
+            Some(build_scanlines(ctx)?)
+        } else {
+            None
+        };
+        Ok(PostProcess {
+            canvas: build_canvas(ctx, resolution)?,
+            resolution: resolution,
+            cover: cover,
+            scanlines: scanlines,
+            flash: 0.0,
+            fade: 0.0,
+            fade_speed: 0.0,
+        })
+    }
+
+    // ウィンドウの大きさに合わせて Canvas を作り直す
+    pub fn resize(&mut self, ctx: &mut Context, window_w_h: Vector2) -> GameResult {
+        let resolution = resolution(window_w_h);
+        if resolution != self.resolution {
+            self.canvas = build_canvas(ctx, resolution)?;
+            self.resolution = resolution;
+        }
+        Ok(())
+    }
+
+    // 不透明度 alpha の白い閃光
+    pub fn flash(&mut self, alpha: f32) {
+        self.flash = self.flash.max(alpha);
+    }
+
+    // 黒から duration [s] かけて現れる
+    pub fn fade_in(&mut self, duration: f32) {
+        self.fade = 1.0;
+        self.fade_speed = -1.0 / duration;
+    }
+
+    // duration [s] かけて黒に消える
+    pub fn fade_out(&mut self, duration: f32) {
+        self.fade_speed = 1.0 / duration;
+    }
+
+    pub fn is_fading_out(&self) -> bool {
+        self.fade_speed > 0.0
+    }
+
+    // fade_out を始めて、画面が黒く覆われきったか
+    pub fn is_faded_out(&self) -> bool {
+        self.is_fading_out() && self.fade >= 1.0
+    }
+
+    // 閃光とフェードを固定ステップで進める
+    pub fn update(&mut self, dt: f32) {
+        const FLASH_DECAY: f32 = 3.0;
+        self.flash = (self.flash - FLASH_DECAY * dt).max(0.0);
+        self.fade = (self.fade + self.fade_speed * dt).clamp(0.0, 1.0);
+    }
+
+    // これ以降の描画を Canvas に向ける。座標はそのまま論理画面のものを使え、camera を通して映る
+    pub fn begin(&mut self, ctx: &mut Context, camera: &Camera) -> GameResult {
+        graphics::set_canvas(ctx, Some(&self.canvas));
+        let playfield = graphics::Rect::new(
+            PLAYFIELD_POS.0,
+            PLAYFIELD_POS.1,
+            PLAYFIELD_W_H.0,
+            PLAYFIELD_W_H.1,
+        );
+        graphics::set_screen_coordinates(ctx, camera.view(playfield))?;
+        graphics::clear(ctx, BACKGROUND_COLOR.into());
+        Ok(())
+    }
+
+    // 描画先を画面に戻し、Canvas に閃光と走査線を重ねる
+    pub fn end(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::set_canvas(ctx, None);
+        let (w, h) = graphics::drawable_size(ctx);
+        graphics::set_screen_coordinates(ctx, layout::letterbox(Vector2(w, h)))?;
+
+        let param = graphics::DrawParam::new()
+            .dest(na::Point2::new(PLAYFIELD_POS.0, PLAYFIELD_POS.1))
+            .scale(na::Vector2::new(
+                1.0 / self.resolution,
+                1.0 / self.resolution,
+            ));
+        graphics::draw(ctx, &self.canvas, param)?;
+
+        if self.flash > 0.0 {
+            let color = graphics::Color::new(1.0, 1.0, 1.0, self.flash);
+            self.draw_cover(ctx, PLAYFIELD_POS, PLAYFIELD_W_H, color)?;
+        }
+        if let Some(scanlines) = &self.scanlines {
+            graphics::draw(ctx, scanlines, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    // 論理画面全体を黒で覆う。HUD も含めて消えるよう、最後に描く
+    pub fn draw_fade(&self, ctx: &mut Context) -> GameResult {
+        if self.fade <= 0.0 {
+            return Ok(());
+        }
+        let color = graphics::Color::new(0.0, 0.0, 0.0, self.fade);
+        self.draw_cover(ctx, Vector2(0.0, 0.0), SCREEN_W_H, color)
+    }
+
+    fn draw_cover(
+        &self,
+        ctx: &mut Context,
+        x_y: Vector2,
+        w_h: Vector2,
+        color: graphics::Color,
+    ) -> GameResult {
+        let param = graphics::DrawParam::new()
+            .dest(na::Point2::new(x_y.0, x_y.1))
+            .scale(na::Vector2::new(w_h.0, w_h.1))
+            .color(color);
+        graphics::draw(ctx, &self.cover, param)
+    }
+}
+
+fn resolution(window_w_h: Vector2) -> f32 {
+    (window_w_h.0 / SCREEN_W_H.0)
+        .min(window_w_h.1 / SCREEN_W_H.1)
+        .max(1.0)
+}
+
+fn build_canvas(ctx: &mut Context, resolution: f32) -> GameResult<graphics::Canvas> {
+    let w_h = PLAYFIELD_W_H * resolution;
+    graphics::Canvas::new(
+        ctx,
+        w_h.0.ceil() as u16,
+        w_h.1.ceil() as u16,
+        NumSamples::One,
+    )
+}
+
+// プレイフィールドに 2px ごとに引く暗い線
+fn build_scanlines(ctx: &mut Context) -> GameResult<graphics::Mesh> {
+    let mut builder = graphics::MeshBuilder::new();
+    let mut y = 0.0;
+    while y < PLAYFIELD_W_H.1 {
+        let rect = graphics::Rect::new(PLAYFIELD_POS.0, PLAYFIELD_POS.1 + y, PLAYFIELD_W_H.0, 1.0);
+        builder.rectangle(graphics::DrawMode::fill(), rect, SCANLINE_COLOR);
+        y += 2.0;
+    }
+    builder.build(ctx)
+}
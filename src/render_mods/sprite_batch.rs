@@ -0,0 +1,190 @@
+use ggez::graphics;
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+
+use std::collections::HashMap;
+
+use crate::actor_mods::actor::*;
+use crate::assets::{Assets, Sprite};
+use crate::collision_mods::hitbox::Hitbox;
+use crate::draw::draw_text;
+use crate::render_mods::layer::Layer;
+use crate::vector2::Vector2;
+
+// 当たり判定の形を区別するキー。f32 はそのままでは Hash できないのでビット列で持つ
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum OutlineKey {
+    Circle(u32),
+    Rect(u32, u32),
+    Capsule(u32, u32),
+}
+
+impl From<Hitbox> for OutlineKey {
+    fn from(hitbox: Hitbox) -> Self {
+        match hitbox {
+            Hitbox::Circle { radius } => OutlineKey::Circle(radius.to_bits()),
+            Hitbox::Rect { half_w, half_h } => OutlineKey::Rect(half_w.to_bits(), half_h.to_bits()),
+            Hitbox::Capsule { half_len, radius } => {
+                OutlineKey::Capsule(half_len.to_bits(), radius.to_bits())
+            }
+        }
+    }
+}
+
+// スプライトを描画の層と画像ごとの SpriteBatch にまとめ、1 枚につき 1 回の描画で済ませる
+// add で溜めて、flush で層ごとにまとめて描く
+pub struct SpriteRenderer {
+    // (層, 画像のパス) ごとの SpriteBatch。層の順に並べ、同じ層の中では最初に使った順に描く
+    batches: Vec<(Layer, String, SpriteBatch)>,
+    // 画像を使わないレーザー。同じ層の SpriteBatch の後に描く
+    beams: Vec<(Layer, Actor)>,
+    // レーザーに使う 1x1 の四角。一度だけ作り、位置と向き、太さと長さは DrawParam で与える
+    beam_mesh: Option<graphics::Mesh>,
+    // 機体の上に出す残りライフ
+    labels: Vec<(Vector2, String)>,
+    // 当たり判定の輪郭。形ごとに一度だけ作り、位置と向きは DrawParam で与える
+    outlines: HashMap<OutlineKey, graphics::Mesh>,
+    hitboxes: Vec<(Actor, graphics::Color)>,
+    show_hitboxes: bool,
+}
+
+impl SpriteRenderer {
+    pub fn new() -> Self {
+        SpriteRenderer {
+            batches: Vec::new(),
+            beams: Vec::new(),
+            beam_mesh: None,
+            labels: Vec::new(),
+            outlines: HashMap::new(),
+            hitboxes: Vec::new(),
+            show_hitboxes: false,
+        }
+    }
+
+    pub fn toggle_hitboxes(&mut self) {
+        self.show_hitboxes = !self.show_hitboxes;
+    }
+
+    pub fn is_showing_hitboxes(&self) -> bool {
+        self.show_hitboxes
+    }
+
+    // actor を種類に応じた画像で layer に 1 つ溜める
+    pub fn add(&mut self, layer: Layer, actor: Actor, assets: &Assets, coords: (f32, f32)) {
+        if actor.get_kind() != BulletKind::Normal {
+            self.beams.push((layer, actor));
+        } else {
+            self.add_sprite(layer, actor, assets.actor_sprite(actor), coords);
+        }
+    }
+
+    // actor を sprite で layer に 1 つ溜める
+    pub fn add_sprite(&mut self, layer: Layer, actor: Actor, sprite: &Sprite, coords: (f32, f32)) {
+        let sheet = sprite.get_sheet();
+        let i = match self
+            .batches
+            .iter()
+            .position(|(l, s, _)| *l == layer && s == sheet)
+        {
+            Some(i) => i,
+            None => {
+                // 手前の層より前に差し込み、層の順を保つ
+                let i = self
+                    .batches
+                    .iter()
+                    .position(|(l, _, _)| *l > layer)
+                    .unwrap_or(self.batches.len());
+                let batch = SpriteBatch::new(sprite.get_image().clone());
+                self.batches.insert(i, (layer, sheet.to_string(), batch));
+                i
+            }
+        };
+        self.batches[i].2.add(actor.sprite_param(sprite, coords));
+    }
+
+    // ワールド座標 x_y に文字を出す。デバッグ表示の層に描く
+    pub fn add_label(&mut self, x_y: Vector2, text: String) {
+        self.labels.push((x_y, text));
+    }
+
+    // 当たり判定の表示が有効なときだけ、輪郭を描く予定に加える。デバッグ表示の層に描く
+    pub fn add_hitbox(&mut self, actor: Actor, color: graphics::Color) {
+        if self.show_hitboxes {
+            self.hitboxes.push((actor, color));
+        }
+    }
+
+    // layer に溜めたものをまとめて描き、次のフレームのために空にする
+    pub fn flush(
+        &mut self,
+        ctx: &mut Context,
+        layer: Layer,
+        assets: &Assets,
+        coords: (f32, f32),
+    ) -> GameResult {
+        for (_, _, batch) in self.batches.iter_mut().filter(|(l, _, _)| *l == layer) {
+            graphics::draw(ctx, batch, graphics::DrawParam::default())?;
+            batch.clear();
+        }
+        for &(_, beam) in self.beams.iter().filter(|(l, _)| *l == layer) {
+            let param = match beam.beam_param(coords) {
+                Some(param) => param,
+                None => continue,
+            };
+            if self.beam_mesh.is_none() {
+                self.beam_mesh = Some(beam_mesh(ctx)?);
+            }
+            if let Some(mesh) = &self.beam_mesh {
+                graphics::draw(ctx, mesh, param)?;
+            }
+        }
+        self.beams.retain(|(l, _)| *l != layer);
+        if layer != Layer::Debug {
+            return Ok(());
+        }
+
+        for (x_y, text) in self.labels.drain(..) {
+            draw_text(ctx, text, x_y, 12.0, assets.get_font(), coords)?;
+        }
+        for (actor, color) in self.hitboxes.drain(..) {
+            let hitbox = actor.get_hitbox();
+            let key = OutlineKey::from(hitbox);
+            if !self.outlines.contains_key(&key) {
+                self.outlines.insert(key, outline_mesh(ctx, hitbox)?);
+            }
+            let x_y = actor.get_x_y().world_to_screen_coords(coords);
+            let param = graphics::DrawParam::new()
+                .dest(na::Point2::new(x_y.0, x_y.1))
+                .rotation(actor.hitbox_angle())
+                .color(color);
+            graphics::draw(ctx, &self.outlines[&key], param)?;
+        }
+        Ok(())
+    }
+}
+
+// 原点を中心とした 1x1 の白い四角
+fn beam_mesh(ctx: &mut Context) -> GameResult<graphics::Mesh> {
+    graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        graphics::Rect::new(-0.5, -0.5, 1.0, 1.0),
+        graphics::WHITE,
+    )
+}
+
+// 原点を中心とした白い輪郭。画面座標は y が下向きなので上下を反転しておく
+fn outline_mesh(ctx: &mut Context, hitbox: Hitbox) -> GameResult<graphics::Mesh> {
+    let points: Vec<na::Point2<f32>> = hitbox
+        .outline(Vector2(0.0, 0.0), 0.0)
+        .into_iter()
+        .map(|p| na::Point2::new(p.0, -p.1))
+        .collect();
+    graphics::Mesh::new_polygon(
+        ctx,
+        graphics::DrawMode::stroke(2.0),
+        &points,
+        graphics::WHITE,
+    )
+}
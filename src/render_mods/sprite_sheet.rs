@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+// スプライトシートの目次。resources/sheets/<名前>.toml に置き、画像と同じ名前にする
+// image = "/sheets/sprites.png"
+// [[frame]]
+// name = "shot"
+// x = 0
+// y = 0
+// w = 16
+// h = 16
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SheetIndex {
+    // ggez の resources からのパス
+    pub image: String,
+    pub frame: Vec<Frame>,
+}
+
+// シートの中の 1 枚。位置と大きさは [px]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl SheetIndex {
+    pub fn load(path: &str) -> Result<SheetIndex, Box<dyn Error>> {
+        let index: SheetIndex = toml::from_str(&fs::read_to_string(path)?)?;
+        for (i, frame) in index.frame.iter().enumerate() {
+            if index.frame[..i].iter().any(|f| f.name == frame.name) {
+                return Err(format!("{}: duplicate frame: {}", path, frame.name).into());
+            }
+        }
+        Ok(index)
+    }
+}
+
+// 大きさ sizes の画像を幅 max_width 以内に並べる
+// 背の高い順に左から詰め、はみ出したら次の段へ送る (シェルフ法)
+// 返り値は (シートの幅, 高さ, 各画像の左上の位置)。隣と滲まないよう padding [px] ずつ空ける
+pub fn pack(
+    sizes: &[(u32, u32)],
+    max_width: u32,
+    padding: u32,
+) -> Result<(u32, u32, Vec<(u32, u32)>), String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        sizes[b]
+            .1
+            .cmp(&sizes[a].1)
+            .then(sizes[b].0.cmp(&sizes[a].0))
+    });
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_h, mut width) = (padding, padding, 0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if w + padding * 2 > max_width {
+            return Err(format!(
+                "{}px wide image does not fit in {}px",
+                w, max_width
+            ));
+        }
+        if x + w + padding > max_width {
+            x = padding;
+            y += shelf_h + padding;
+            shelf_h = 0;
+        }
+        positions[i] = (x, y);
+        x += w + padding;
+        shelf_h = shelf_h.max(h);
+        width = width.max(x);
+    }
+    let height = y + shelf_h + padding;
+    // 古い GPU のために 2 の累乗にそろえる。幅は max_width を超えない範囲で
+    let width = match width.next_power_of_two() {
+        w if w <= max_width => w,
+        _ => width,
+    };
+    Ok((width, height.next_power_of_two(), positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    // 置いた四角 (x, y, w, h) の一覧
+    fn rects(sizes: &[(u32, u32)], positions: &[(u32, u32)]) -> Vec<(u32, u32, u32, u32)> {
+        sizes
+            .iter()
+            .zip(positions)
+            .map(|(&(w, h), &(x, y))| (x, y, w, h))
+            .collect()
+    }
+
+    #[test]
+    fn packed_images_do_not_overlap_and_fit() {
+        let sizes: Vec<(u32, u32)> = (0..40)
+            .map(|i| (8 + (i * 7) % 41, 8 + (i * 13) % 29))
+            .collect();
+        for &max_width in &[64, 100, 256] {
+            let (width, height, positions) = pack(&sizes, max_width, 2).unwrap();
+            assert!(width <= max_width);
+            let rects = rects(&sizes, &positions);
+            for (i, &a) in rects.iter().enumerate() {
+                assert!(a.0 + a.2 <= width && a.1 + a.3 <= height);
+                for &b in &rects[i + 1..] {
+                    assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn padding_separates_neighbours() {
+        let sizes = [(10, 10), (10, 10), (10, 10)];
+        let (_, _, positions) = pack(&sizes, 64, 3).unwrap();
+        // 周りに padding だけの隙間を空けても重ならない
+        let padded: Vec<(u32, u32, u32, u32)> = rects(&sizes, &positions)
+            .into_iter()
+            .map(|(x, y, w, h)| (x - 3, y - 3, w + 3, h + 3))
+            .collect();
+        for (i, &a) in padded.iter().enumerate() {
+            for &b in &padded[i + 1..] {
+                assert!(!overlaps(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn too_wide_image_is_an_error() {
+        assert!(pack(&[(64, 8)], 64, 2).is_err());
+        assert!(pack(&[(60, 8)], 64, 2).is_ok());
+    }
+}
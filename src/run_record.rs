@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::difficulty::{Difficulty, Rank};
+
+// 直前に始めたプレイの乱数の種とランクの設定。どの条件で遊んだかを後から確かめるために書き出す
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    // ゲームの乱数の種
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub dynamic_rank: bool,
+}
+
+impl RunRecord {
+    pub fn new(seed: u64, rank: &Rank) -> Self {
+        RunRecord {
+            seed: seed,
+            difficulty: rank.get_difficulty(),
+            dynamic_rank: rank.is_dynamic(),
+        }
+    }
+
+    // dir が無ければ作ってから、その中の file_name に書き出す
+    pub fn save(&self, dir: &Path, file_name: &str) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(file_name), toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_the_starting_rank_settings() {
+        let mut rank = Rank::new(Difficulty::Lunatic, true);
+        // 途中で動いたランクではなく、始まりの設定を残す
+        rank.update(10.0, 50_000);
+        let record = RunRecord::new(42, &rank);
+        let dir = std::env::temp_dir().join("my_first_ggez_run_record_test");
+        record.save(&dir, "last_run.toml").unwrap();
+        let text = fs::read_to_string(dir.join("last_run.toml")).unwrap();
+        let saved: RunRecord = toml::from_str(&text).unwrap();
+        assert_eq!(saved.seed, 42);
+        assert_eq!(saved.difficulty, Difficulty::Lunatic);
+        assert!(saved.dynamic_rank);
+    }
+}
@@ -1,50 +1,148 @@
 use ggez;
 
 use ggez::event::{self, EventHandler, KeyCode, KeyMods, MouseButton};
+use ggez::filesystem;
 use ggez::graphics;
 use ggez::timer;
 use ggez::{Context, GameResult};
 
-use csv::Reader;
 use oorandom::Rand32;
 use std::error::Error;
-use std::str::FromStr;
 
 use crate::actor_mods::actor::*;
 use crate::actor_mods::actor_state::*;
-use crate::assets::Assets;
-use crate::draw::draw_text;
+use crate::actor_mods::boss::Boss;
+use crate::assets::{actor_image_name, Assets};
+use crate::audio_mods::audio_manager::{AudioConfig, AudioManager};
+use crate::collision_mods::spatial_hash::SpatialHash;
+use crate::difficulty::Rank;
+use crate::draw::draw_screen_text;
+use crate::game_event::GameEvent;
 use crate::imgui_wrapper::ImGuiWrapper;
 use crate::input::*;
-use crate::vector2::Vector2;
+use crate::path_mods::path::PathBook;
+use crate::pattern_mods::bulletml;
+use crate::pattern_mods::pattern::PatternBook;
+use crate::render_mods::background::Background;
+use crate::render_mods::camera::Camera;
+use crate::render_mods::layer::Layer;
+use crate::render_mods::layout::{self, HUD_POS, PLAYFIELD_W_H};
+use crate::render_mods::particles::{Emitter, ParticleSystem};
+use crate::render_mods::post_process::{PostProcess, VideoConfig};
+use crate::render_mods::sprite_batch::SpriteRenderer;
+use crate::run_record::RunRecord;
+use crate::stage_mods::stage::Stage;
+use crate::vector2::{vec_from_angle, Vector2};
+
+const BOMB_STOCK: u32 = 3;
+// ステージの始まりと終わりに暗転する時間 [s]
+const FADE_TIME: f32 = 1.0;
+// ゲームの乱数の種。今は毎回同じ
+const RUN_SEED: u64 = 0;
+// 最後に始めたプレイの設定を書き出すファイル。ggez のユーザーデータのディレクトリに置く
+const RUN_RECORD_FILE: &str = "last_run.toml";
 
 // TODO #4
 pub struct MainScene {
     player_state: (ActorState, Vec<ActorState>), // (親機, 子機) TODO #5
     enemies_state: Vec<ActorState>,
+    stage: Stage,
+    background: Background,
+    // ステージ開始からの経過時間と、次に出すウェーブ
+    stage_time: f32,
+    next_wave: usize,
+    // 道中の敵がいなくなると現れる。本体の ActorState は enemies_state に入る
+    boss: Option<Boss>,
+    patterns: PatternBook,
+    paths: PathBook,
     imgui_wrapper: ImGuiWrapper,
     assets: Assets,
-    screen_w_h: Vector2,
+    audio: AudioManager,
+    renderer: SpriteRenderer,
+    // プレイフィールドの枠と黒帯
+    frame: graphics::Mesh,
+    // 閃光や暗転
+    post_process: PostProcess,
+    // プレイフィールドを映すカメラ。揺れとズームを持つ
+    camera: Camera,
     hidpi_factor: f32,
     input: InputState,
-    _rng: Rand32,
+    events: Vec<GameEvent>,
+    score: u32,
+    // かすった敵弾の数
+    graze: u32,
+    // 打ち消した弾や倒した敵から出た得点アイテム
+    items: Vec<Actor>,
+    // 爆発などの演出。アニメーションが終わると消える
+    effects: Vec<Actor>,
+    particles: ParticleSystem,
+    player_trail: Option<Emitter>,
+    // 打ち消した敵弾の数
+    cancelled: u32,
+    // 打ち消した弾から出た得点アイテムを拾って得た得点の合計
+    cancel_bonus: u32,
+    bombs: u32,
+    shot_grid: SpatialHash,
+    candidates: Vec<(u32, u32)>,
+    // 自機の誘導弾が狙う敵の位置
+    targets: Vec<Vector2>,
+    // 難易度。config/config.toml で選ぶ
+    rank: Rank,
+    rng: Rand32,
 }
 
 impl MainScene {
     pub fn new(ctx: &mut Context, hidpi_factor: f32) -> GameResult<MainScene> {
-        let seed: [u8; 8] = [0; 8];
-        let mut _rng = Rand32::new(u64::from_ne_bytes(seed));
+        const GRID_CELL_SIZE: f32 = 32.0;
+        let rng = Rand32::new(RUN_SEED);
+        let (w, h) = graphics::drawable_size(ctx);
+        graphics::set_screen_coordinates(ctx, layout::letterbox(Vector2(w, h)))?;
+        let video = VideoConfig::load("./config/config.toml").unwrap_or_else(|err| {
+            println!("{}", err);
+            VideoConfig::default()
+        });
+        let player = PlayerConfig::load("./config/config.toml").unwrap_or_else(|err| {
+            println!("{}", err);
+            PlayerConfig::default()
+        });
+        let mut post_process = PostProcess::new(ctx, video)?;
+        post_process.fade_in(FADE_TIME);
 
         let state = MainScene {
-            player_state: (ActorState::new(create_player()), Vec::new()),
+            player_state: (
+                ActorState::new(create_player()).with_homing_shots(player.homing_shots),
+                Vec::new(),
+            ),
             enemies_state: Vec::new(),
+            stage: Stage::new(),
+            background: Background::new(),
+            stage_time: 0.0,
+            next_wave: 0,
+            boss: None,
+            patterns: PatternBook::new(),
+            paths: PathBook::new(),
             assets: Assets::new(ctx)?.load(ctx).unwrap(),
-            screen_w_h: Vector2(
-                graphics::drawable_size(ctx).0,
-                graphics::drawable_size(ctx).1,
-            ),
+            audio: AudioManager::new(),
+            renderer: SpriteRenderer::new(),
+            frame: layout::build_frame(ctx)?,
+            post_process: post_process,
+            camera: Camera::new().with_shake(video.screen_shake),
             input: InputState::default(),
-            _rng: _rng,
+            events: Vec::new(),
+            score: 0,
+            graze: 0,
+            items: Vec::new(),
+            effects: Vec::new(),
+            particles: ParticleSystem::new(),
+            player_trail: None,
+            cancelled: 0,
+            cancel_bonus: 0,
+            bombs: BOMB_STOCK,
+            shot_grid: SpatialHash::new(PLAYFIELD_W_H, GRID_CELL_SIZE),
+            candidates: Vec::new(),
+            targets: Vec::new(),
+            rank: Rank::default(),
+            rng: rng,
             imgui_wrapper: ImGuiWrapper::new(ctx),
             hidpi_factor: hidpi_factor,
         };
@@ -52,8 +150,51 @@ impl MainScene {
         Ok(state)
     }
 
-    pub fn load_data(&mut self) -> GameResult {
-        if let Err(err) = self.load_enemy_data() {
+    pub fn load_data(&mut self, ctx: &mut Context) -> GameResult {
+        match Rank::load("./config/config.toml") {
+            Ok(rank) => self.rank = rank,
+            Err(err) => println!("{}", err),
+        }
+        // どの条件で遊んだかわかるよう、乱数の種とランクの設定を残す
+        let record = RunRecord::new(RUN_SEED, &self.rank);
+        if let Err(err) = record.save(filesystem::user_data_dir(ctx), RUN_RECORD_FILE) {
+            println!("{}", err);
+        }
+        let audio_config = AudioConfig::load("./config/config.toml").unwrap_or_else(|err| {
+            println!("{}", err);
+            AudioConfig::default()
+        });
+        match AudioManager::load(ctx, "./data/sounds.toml", audio_config) {
+            Ok(audio) => self.audio = audio,
+            Err(err) => println!("{}", err),
+        }
+        match PatternBook::load("./data/patterns.toml") {
+            Ok(patterns) => self.patterns = patterns,
+            Err(err) => println!("{}", err),
+        }
+        if let Err(err) = bulletml::import_dir(&mut self.patterns, "./data/bulletml") {
+            println!("{}", err);
+        }
+        match ParticleSystem::load("./data/particles.toml") {
+            Ok(particles) => {
+                let assets = &self.assets;
+                if let Err(err) = particles.validate(|name| assets.has_sprite(name)) {
+                    println!("{}", err);
+                }
+                self.player_trail = particles.emitter("player_trail");
+                self.particles = particles;
+            }
+            Err(err) => println!("{}", err),
+        }
+        match PathBook::load("./data/paths.toml") {
+            Ok(paths) => self.paths = paths,
+            Err(err) => println!("{}", err),
+        }
+        match Boss::load("./data/bosses.toml", &self.patterns, &self.paths) {
+            Ok(boss) => self.boss = Some(boss),
+            Err(err) => println!("{}", err),
+        }
+        if let Err(err) = self.load_stage(ctx) {
             println!("{}", err);
         // process::exit(1);
         } else {
@@ -62,78 +203,331 @@ impl MainScene {
         Ok(())
     }
 
-    fn load_enemy_data(&mut self) -> Result<(), Box<dyn Error>> {
-        // ステージ制を導入する際は、CSVファイルの読み分けが必要
-        let mut rdr = Reader::from_path("./data/enemy_state.csv")?;
-        for result in rdr.records() {
-            let record = result?;
-            // デシリアライズの方法が不明なため、要素を逐一代入
-            let enemy = Actor::new(
-                ActorType::from_str(&record[0])?,
-                Vector2(record[1].parse()?, record[2].parse()?),
-                Vector2(record[3].parse()?, record[4].parse()?),
-                record[5].parse()?,
-                Vector2(record[6].parse()?, record[7].parse()?),
-                record[8].parse()?,
-                record[9].parse()?,
-                record[10].parse()?,
-            );
-            let enemy_state = ActorState::new(enemy);
-            // println!("{:#?}", enemy_state);
-            self.enemies_state.push(enemy_state);
+    fn load_stage(&mut self, ctx: &mut Context) -> Result<(), Box<dyn Error>> {
+        let assets = &self.assets;
+        self.stage = Stage::load(
+            "./data/enemies.toml",
+            "./data/stage.toml",
+            &self.patterns,
+            &self.paths,
+            |name| assets.has_sprite(name),
+        )?;
+        self.background = Background::load(ctx, self.stage.get_backgrounds())?;
+        if let Some(bgm) = self.stage.get_bgm() {
+            self.audio.play_bgm(ctx, bgm)?;
         }
         Ok(())
     }
 
+    // 出現時刻を過ぎたウェーブの敵を出す
+    fn spawn_waves(&mut self, dt: f32) {
+        self.stage_time += dt;
+        while let Some(wave) = self.stage.get_waves().get(self.next_wave) {
+            if wave.time > self.stage_time {
+                break;
+            }
+            for enemy in &wave.enemies {
+                self.enemies_state.push(enemy.spawn(self.rank.hp_scale()));
+            }
+            self.next_wave += 1;
+        }
+    }
+
+    // ボムやボスのフェーズの終わりに、敵弾をすべて得点アイテムに変える
+    fn cancel_enemy_shots(&mut self) {
+        let mut count = 0;
+        for enemy_state in &mut self.enemies_state {
+            count += enemy_state.cancel_shots(&mut self.items);
+        }
+        self.events.push(GameEvent::Cancel { count: count });
+    }
+
+    fn bomb(&mut self) {
+        if self.bombs == 0 {
+            return;
+        }
+        self.bombs -= 1;
+        self.events.push(GameEvent::Bomb {
+            x_y: self.player_state.0.get_actor().get_x_y(),
+        });
+        if let Some(boss) = &mut self.boss {
+            boss.fail_card();
+        }
+        self.cancel_enemy_shots();
+    }
+
+    // 道中の敵がいなくなったらボスを出し、フェーズが終わるたびに敵弾を打ち消す
+    fn update_boss(&mut self, dt: f32) {
+        let boss = match &mut self.boss {
+            Some(boss) => boss,
+            None => return,
+        };
+        if !boss.is_spawned() {
+            let cleared = self.next_wave >= self.stage.get_waves().len()
+                && self
+                    .enemies_state
+                    .iter()
+                    .all(|es| es.get_actor().get_life() <= 0);
+            if cleared {
+                self.enemies_state.push(boss.spawn(self.rank.hp_scale()));
+            }
+            return;
+        }
+        let state = self
+            .enemies_state
+            .iter_mut()
+            .find(|es| es.get_actor().get_tag() == ActorType::Boss);
+        let bonus = match state {
+            Some(state) => boss.update(state, dt),
+            None => None,
+        };
+        if let Some(bonus) = bonus {
+            self.events.push(GameEvent::PhaseCleared { bonus: bonus });
+            self.cancel_enemy_shots();
+        }
+    }
+
+    fn update_items(&mut self, dt: f32) {
+        let target = self.player_state.0.get_actor().get_x_y();
+        for item in &mut self.items {
+            item.steer_towards(target, dt);
+            item.update_actor_position(dt);
+        }
+    }
+
+    fn update_effects(&mut self, dt: f32) {
+        for effect in &mut self.effects {
+            effect.update_actor_position(dt);
+        }
+    }
+
     fn clear_dead_stuff(&mut self) {
-        self.player_state.0.clear_dead_stuff(self.screen_w_h);
+        self.items.retain(|item| item.get_life() > 0);
+        let assets = &self.assets;
+        self.effects.retain(|effect| {
+            !assets.is_animation_finished(actor_image_name(*effect), effect.get_age())
+        });
+        self.player_state.0.clear_dead_stuff(PLAYFIELD_W_H);
         for enemy_state in &mut self.enemies_state {
-            enemy_state.clear_dead_stuff(self.screen_w_h);
+            enemy_state.clear_dead_stuff(PLAYFIELD_W_H);
         }
-        self.enemies_state
-            .retain(|es| es.get_actor().get_life() > 0)
+        self.enemies_state.retain(|es| !es.is_finished())
     }
 
     fn handle_collisions(&mut self, _ctx: &Context) {
+        let events = &mut self.events;
+        let grid = &mut self.shot_grid;
+        let candidates = &mut self.candidates;
+
+        // 敵弾 → 自機
+        grid.clear();
+        for (i, enemy_state) in self.enemies_state.iter().enumerate() {
+            for (j, shot) in enemy_state.get_shots().iter().enumerate() {
+                grid.insert((i as u32, j as u32), shot.get_x_y(), shot.bounding_radius());
+            }
+        }
+        // 当たらずに GRAZE_RADIUS 以内を通った弾はかすりになる
+        const GRAZE_RADIUS: f32 = 16.0;
+        let player = self.player_state.0.get_mut_actor();
+        candidates.clear();
+        grid.query(
+            player.get_x_y(),
+            player.bounding_radius() + GRAZE_RADIUS,
+            candidates,
+        );
+        for &(i, j) in candidates.iter() {
+            let shot = &mut self.enemies_state[i as usize].get_mut_shots()[j as usize];
+            if player.handle_actor_collision(shot) {
+                events.push(GameEvent::Hit {
+                    target: ActorType::Player,
+                    x_y: shot.get_x_y(),
+                    killed: player.get_life() <= 0,
+                });
+            } else if shot.graze(player, GRAZE_RADIUS) {
+                events.push(GameEvent::Graze {
+                    x_y: shot.get_x_y(),
+                });
+            }
+        }
+
+        // 得点アイテム → 自機。少し離れていても拾える
+        const ITEM_COLLECT_RADIUS: f32 = 32.0;
+        for item in &mut self.items {
+            if (item.get_x_y() - player.get_x_y()).norm() < ITEM_COLLECT_RADIUS {
+                item.consume_all();
+                events.push(GameEvent::ItemCollected {
+                    x_y: item.get_x_y(),
+                    cancelled: item.get_item_origin() == Some(ItemOrigin::Cancel),
+                });
+            }
+        }
+
+        // 自機弾 → 敵
+        grid.clear();
+        for (j, shot) in self.player_state.0.get_shots().iter().enumerate() {
+            grid.insert((0, j as u32), shot.get_x_y(), shot.bounding_radius());
+        }
+        let player_shots = self.player_state.0.get_mut_shots();
+        let items = &mut self.items;
         for enemy_state in &mut self.enemies_state {
-            for shot in enemy_state.get_shots() {
-                self.player_state
-                    .0
-                    .get_mut_actor()
-                    .handle_actor_collision(*shot)
+            let drops = enemy_state.get_drops();
+            let enemy = enemy_state.get_mut_actor();
+            candidates.clear();
+            grid.query(enemy.get_x_y(), enemy.bounding_radius(), candidates);
+            for &(_, j) in candidates.iter() {
+                let shot = &mut player_shots[j as usize];
+                if enemy.handle_actor_collision(shot) {
+                    let killed = enemy.get_life() <= 0;
+                    events.push(GameEvent::Hit {
+                        target: enemy.get_tag(),
+                        x_y: shot.get_x_y(),
+                        killed: killed,
+                    });
+                    if killed {
+                        // 倒した敵の周りに得点アイテムを散らす
+                        const DROP_SPREAD: f32 = 12.0;
+                        for i in 0..drops {
+                            let angle = i as f32 / drops as f32 * 2.0 * std::f32::consts::PI;
+                            let x_y = enemy.get_x_y() + vec_from_angle(angle) * DROP_SPREAD;
+                            items.push(create_item(x_y, ItemOrigin::Drop));
+                        }
+                    }
+                }
             }
-            for shot in self.player_state.0.get_shots() {
-                enemy_state.get_mut_actor().handle_actor_collision(*shot)
+        }
+    }
+
+    fn handle_events(&mut self) {
+        for event in self.events.drain(..) {
+            match event {
+                GameEvent::Hit {
+                    target: ActorType::Player,
+                    x_y,
+                    killed,
+                } => {
+                    if let Some(boss) = &mut self.boss {
+                        boss.fail_card();
+                    }
+                    if killed {
+                        self.rank.on_death();
+                    }
+                    self.particles.burst("player_hit", x_y);
+                    self.audio.play(if killed { "death" } else { "hit" });
+                    // 撃墜されたときはより大きく揺らす
+                    self.camera.add_trauma(if killed { 1.0 } else { 0.6 });
+                }
+                GameEvent::Hit {
+                    target,
+                    x_y,
+                    killed,
+                    ..
+                } => {
+                    const HIT_SCORE: u32 = 10;
+                    const KILL_SCORE: u32 = 100;
+                    self.score += if killed { KILL_SCORE } else { HIT_SCORE };
+                    if killed {
+                        self.effects.push(create_explosion(x_y));
+                        self.particles.burst("explosion", x_y);
+                        self.audio.play("explosion");
+                        if target == ActorType::Boss {
+                            if let Some(boss) = &mut self.boss {
+                                boss.break_phase();
+                            }
+                            self.camera.add_trauma(0.8);
+                            self.camera.punch_zoom(0.15);
+                        }
+                    } else {
+                        self.particles.burst("hit_spark", x_y);
+                        self.audio.play("hit");
+                    }
+                }
+                GameEvent::Shot { .. } => self.audio.play("shot"),
+                GameEvent::Cancel { count } => self.cancelled += count,
+                GameEvent::PhaseCleared { bonus } => self.score += bonus,
+                GameEvent::ItemCollected { cancelled, .. } => {
+                    const ITEM_SCORE: u32 = 10;
+                    self.score += ITEM_SCORE;
+                    if cancelled {
+                        self.cancel_bonus += ITEM_SCORE;
+                    }
+                    self.audio.play("item");
+                }
+                GameEvent::Graze { x_y } => {
+                    self.graze += 1;
+                    self.particles.burst("graze", x_y);
+                    self.audio.play("graze");
+                }
+                GameEvent::Bomb { x_y } => {
+                    self.particles.burst("bomb", x_y);
+                    self.audio.play("bomb");
+                    self.post_process.flash(0.6);
+                    self.camera.add_trauma(0.4);
+                    self.camera.punch_zoom(0.05);
+                }
+            }
+        }
+    }
+
+    // 右側の HUD。得点や残りなどを出す
+    fn draw_hud(&self, ctx: &mut Context) -> GameResult {
+        let font = self.assets.get_font();
+        let player = self.player_state.0.get_actor();
+        draw_screen_text(
+            ctx,
+            format!(
+                "Score   {:>9}\nLife    {:>9}\nBomb    {:>9}\nGraze   {:>9}\nCancel  {:>9}\nBonus   {:>9}\n\n{:?}\nRank    {:>9.2}{}",
+                self.score,
+                player.get_life().max(0),
+                self.bombs,
+                self.graze,
+                self.cancelled,
+                self.cancel_bonus,
+                self.rank.get_difficulty(),
+                self.rank.value(),
+                if self.rank.is_dynamic() { "\n(dynamic)" } else { "" },
+            ),
+            HUD_POS + Vector2(8.0, 16.0),
+            16.0,
+            font,
+        )
+    }
+
+    fn draw_boss_hud(&self, ctx: &mut Context, coords: (f32, f32)) -> GameResult {
+        if let Some(boss) = &self.boss {
+            let state = self
+                .enemies_state
+                .iter()
+                .find(|es| es.get_actor().get_tag() == ActorType::Boss);
+            if let Some(state) = state {
+                boss.draw_hud(ctx, state, &self.assets, coords)?;
             }
         }
+        Ok(())
     }
 
+    // HUD の下に出すデバッグ用の情報
     fn draw_debug_status(&self, ctx: &mut Context) -> GameResult {
-        let text_pos = Vector2(10.0, 10.0);
-        let font_size = 24.0;
-        let all_shot_num = &mut self.player_state.0.get_shots().len();
+        let font = self.assets.get_font();
+        let player = self.player_state.0.get_actor();
+        let mut all_shot_num = self.player_state.0.get_shots().len();
         for enemy_state in &self.enemies_state {
-            *all_shot_num += enemy_state.get_shots().len();
+            all_shot_num += enemy_state.get_shots().len();
         }
-        draw_text(
+        draw_screen_text(
             ctx,
             format!(
-                "
-FPS: {}\n
-time: {}\n
-bullet_num: {}\n
-Player:\n
-{:#?}
-                ",
-                timer::fps(ctx) as f32,
+                "FPS: {:.1}\ntime: {:.1}\nbullets: {}\nparticles: {}\nhitbox (F3): {}\nplayer: ({:.0}, {:.0})",
+                timer::fps(ctx),
                 timer::time_since_start(ctx).as_secs_f32(),
                 all_shot_num,
-                self.player_state.0.get_actor(),
+                self.particles.get_count(),
+                self.renderer.is_showing_hitboxes(),
+                player.get_x_y().0,
+                player.get_x_y().1,
             ),
-            text_pos,
-            font_size,
-            self.assets.get_font(),
-            (0.0, 0.0),
+            HUD_POS + Vector2(8.0, 320.0),
+            12.0,
+            font,
         )
     }
 }
@@ -149,41 +543,120 @@ impl EventHandler for MainScene {
                 let player_state = &mut self.player_state.0;
                 player_state.handle_input(&self.input, seconds);
                 if self.input.get_fire() && player_state.get_shot_timeout() < 0.0 {
-                    player_state.fire_shot(ctx);
+                    player_state.fire_shot();
+                    self.events.push(GameEvent::Shot {
+                        x_y: player_state.get_actor().get_x_y(),
+                    });
+                }
+                self.targets.clear();
+                self.targets.extend(
+                    self.enemies_state
+                        .iter()
+                        .map(|es| es.get_actor())
+                        .filter(|enemy| enemy.get_life() > 0)
+                        .map(|enemy| enemy.get_x_y()),
+                );
+                player_state.steer_shots(&self.targets, seconds);
+                player_state.update(seconds, PLAYFIELD_W_H);
+                let player = player_state.get_actor();
+                if let Some(trail) = &mut self.player_trail {
+                    if player.get_life() > 0 {
+                        self.particles.emit(trail, player.get_x_y(), seconds);
+                    }
                 }
-                player_state.update(seconds, self.screen_w_h);
 
-                if self.player_state.0.get_actor().get_life() <= 0 {
+                // 撃墜されたら暗転してから終わる
+                if self.player_state.0.get_actor().get_life() <= 0
+                    && !self.post_process.is_fading_out()
+                {
                     println!("Game over!!");
-                    event::quit(ctx);
+                    self.post_process.fade_out(FADE_TIME);
+                    self.audio.stop_bgm();
                 }
             }
 
+            self.spawn_waves(seconds);
+            self.background.update(seconds, self.stage_time);
+            if self.input.take_bomb() {
+                self.bomb();
+            }
+
+            let player = self.player_state.0.get_actor();
             for enemy_state in &mut self.enemies_state {
-                if enemy_state.get_shot_timeout() < 0.0 {
-                    enemy_state.fire_shot(ctx);
-                }
-                enemy_state.update(seconds, self.screen_w_h);
+                let (patterns, rank, rng) = (&self.patterns, self.rank.value(), &mut self.rng);
+                enemy_state.run_pattern(patterns, seconds, rank, &player, rng);
+                enemy_state.run_shot_patterns(patterns, seconds, rank, &player, rng);
+                enemy_state.steer_shots(&[player.get_x_y()], seconds);
+                enemy_state.update(seconds, PLAYFIELD_W_H);
             }
 
+            self.update_items(seconds);
+            self.update_effects(seconds);
+
             self.handle_collisions(ctx);
+            self.handle_events();
+            self.particles.update(seconds);
+            self.post_process.update(seconds);
+            self.camera.update(seconds);
+            self.audio.update();
+            self.rank.update(seconds, self.score);
+            self.update_boss(seconds);
             self.clear_dead_stuff();
         }
+        if self.post_process.is_faded_out() {
+            event::quit(ctx);
+        }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, [0.1, 0.2, 0.3, 1.0].into());
-        self.draw_debug_status(ctx)?;
-
-        let assets = &self.assets;
-        let coords = (self.screen_w_h.0, self.screen_w_h.1);
-
-        &self.player_state.0.draw(ctx, assets, coords)?;
+        graphics::clear(ctx, graphics::BLACK);
+
+        let coords = layout::world_coords();
+        {
+            let assets = &self.assets;
+            let renderer = &mut self.renderer;
+            self.player_state.0.draw(assets, renderer, coords);
+            for enemy_state in &self.enemies_state {
+                enemy_state.draw(assets, renderer, coords);
+            }
+            for &item in &self.items {
+                renderer.add(Layer::Items, item, assets, coords);
+            }
+            for &effect in &self.effects {
+                renderer.add(Layer::Effects, effect, assets, coords);
+            }
+        }
 
-        for enemy_state in &self.enemies_state {
-            enemy_state.draw(ctx, assets, coords)?;
+        // 奥の層から順に描く。Hud より奥はプレイフィールドとして Canvas に描く
+        self.post_process.begin(ctx, &self.camera)?;
+        for &layer in &Layer::ALL {
+            match layer {
+                Layer::Background => self.background.draw(ctx, self.stage_time)?,
+                Layer::Hud => {
+                    self.post_process.end(ctx)?;
+                    // プレイフィールドからはみ出した分を枠で隠してから、枠の上に HUD を描く
+                    graphics::draw(ctx, &self.frame, graphics::DrawParam::default())?;
+                    self.draw_hud(ctx)?;
+                    self.draw_boss_hud(ctx, coords)?;
+                }
+                Layer::Debug => {
+                    // 当たり判定などはプレイフィールドと同じくカメラを通して重ねる
+                    let screen = graphics::screen_coordinates(ctx);
+                    graphics::set_screen_coordinates(ctx, self.camera.view(screen))?;
+                    self.renderer.flush(ctx, layer, &self.assets, coords)?;
+                    graphics::set_screen_coordinates(ctx, screen)?;
+                    self.draw_debug_status(ctx)?;
+                }
+                _ => {
+                    self.renderer.flush(ctx, layer, &self.assets, coords)?;
+                    if layer == Layer::Effects {
+                        self.particles.draw(ctx, &self.assets, coords)?;
+                    }
+                }
+            }
         }
+        self.post_process.draw_fade(ctx)?;
 
         // Render game ui
         self.imgui_wrapper.render(ctx, self.hidpi_factor);
@@ -226,7 +699,7 @@ impl EventHandler for MainScene {
         ctx: &mut Context,
         keycode: KeyCode,
         keymods: KeyMods,
-        _repeat: bool,
+        repeat: bool,
     ) {
         match keycode {
             KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
@@ -235,6 +708,11 @@ impl EventHandler for MainScene {
             KeyCode::Z => {
                 self.input.fire_shot(keycode);
             }
+            KeyCode::X if !repeat => {
+                self.input.press_bomb(keycode);
+            }
+            // 当たり判定の表示を切り替える
+            KeyCode::F3 if !repeat => self.renderer.toggle_hitboxes(),
             /* // TODO #6 低速移動の実装
             KeyCode::LShift | KeyCode::RShift => {
                 self.input.xaxis /= 2.0;
@@ -265,8 +743,11 @@ impl EventHandler for MainScene {
         self.imgui_wrapper.update_text(val);
     }
 
+    // ウィンドウの大きさが変わっても、論理画面を縦横比を保ったまま中央に映す
     fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
-        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height))
+        graphics::set_screen_coordinates(ctx, layout::letterbox(Vector2(width, height))).unwrap();
+        self.post_process
+            .resize(ctx, Vector2(width, height))
             .unwrap();
         // println!("{:?}", graphics::screen_coordinates(ctx));
     }
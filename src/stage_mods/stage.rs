@@ -0,0 +1,428 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use crate::actor_mods::actor::*;
+use crate::actor_mods::actor_state::ActorState;
+use crate::collision_mods::hitbox::Hitbox;
+use crate::difficulty::scale_hp;
+use crate::path_mods::path::{Path, PathBook};
+use crate::pattern_mods::pattern::PatternBook;
+use crate::pattern_mods::pattern_runner::PatternRunner;
+use crate::render_mods::background::LayerDef;
+use crate::vector2::Vector2;
+
+const DEFAULT_SPRITE: &str = "enemy";
+const DEFAULT_COLLISION_TIMEOUT: f32 = 0.25;
+
+// テンプレートとウェーブの両方に書ける敵の設定。書かなかった項目は継承元から受け継ぐ
+#[derive(Clone, Debug, Default, Deserialize)]
+struct EnemyFields {
+    sprite: Option<String>,
+    w: Option<f32>,
+    h: Option<f32>,
+    // 省略すると画像の大きさ (w, h) の外接円
+    hitbox: Option<Hitbox>,
+    hp: Option<i32>,
+    max_collision_timeout: Option<f32>,
+    // 倒したときに出る得点アイテムの数
+    drops: Option<u32>,
+    ang_vel: Option<f32>,
+    // 経路を持たない敵の速度
+    vx: Option<f32>,
+    vy: Option<f32>,
+    pattern: Option<String>,
+    path: Option<String>,
+}
+
+impl EnemyFields {
+    // self に書かれていない項目を base で埋める
+    fn inherit(self, base: &EnemyFields) -> EnemyFields {
+        EnemyFields {
+            sprite: self.sprite.or_else(|| base.sprite.clone()),
+            w: self.w.or(base.w),
+            h: self.h.or(base.h),
+            hitbox: self.hitbox.or(base.hitbox),
+            hp: self.hp.or(base.hp),
+            max_collision_timeout: self.max_collision_timeout.or(base.max_collision_timeout),
+            drops: self.drops.or(base.drops),
+            ang_vel: self.ang_vel.or(base.ang_vel),
+            vx: self.vx.or(base.vx),
+            vy: self.vy.or(base.vy),
+            pattern: self.pattern.or_else(|| base.pattern.clone()),
+            path: self.path.or_else(|| base.path.clone()),
+        }
+    }
+}
+
+// data/enemies.toml の敵の雛形。base を書くと別のテンプレートを継承する
+#[derive(Clone, Debug, Deserialize)]
+struct TemplateDef {
+    name: String,
+    #[serde(default)]
+    base: Option<String>,
+    #[serde(flatten)]
+    fields: EnemyFields,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TemplateFile {
+    template: Vec<TemplateDef>,
+}
+
+// data/stage.toml のウェーブに並べる敵。template の設定を個別に上書きできる
+#[derive(Clone, Debug, Deserialize)]
+struct SpawnDef {
+    template: String,
+    x: f32,
+    y: f32,
+    #[serde(flatten)]
+    fields: EnemyFields,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct WaveDef {
+    // ステージ開始から出現までの時間 [s]
+    time: f32,
+    enemies: Vec<SpawnDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct StageFile {
+    // 繰り返し流す BGM。ggez の resources からのパス
+    #[serde(default)]
+    bgm: Option<String>,
+    #[serde(default)]
+    background: Vec<LayerDef>,
+    wave: Vec<WaveDef>,
+}
+
+// 名前を解決し、すべての項目が決まった敵
+#[derive(Clone, Debug)]
+pub struct EnemySpawn {
+    x_y: Vector2,
+    w_h: Vector2,
+    hitbox: Option<Hitbox>,
+    hp: i32,
+    max_collision_timeout: f32,
+    ang_vel: f32,
+    vel: Vector2,
+    sprite: String,
+    drops: u32,
+    patterns: Vec<usize>,
+    path: Option<Path>,
+}
+
+impl EnemySpawn {
+    // hp_scale は難易度による体力の倍率
+    pub fn spawn(&self, hp_scale: f32) -> ActorState {
+        let mut actor = Actor::new(
+            ActorType::Enemy,
+            self.x_y,
+            self.w_h,
+            0.0,
+            self.vel,
+            self.ang_vel,
+            scale_hp(self.hp, hp_scale),
+            self.max_collision_timeout,
+        );
+        if let Some(hitbox) = self.hitbox {
+            actor = actor.with_hitbox(hitbox);
+        }
+        let mut state = ActorState::new(actor);
+        state.set_sprite(&self.sprite);
+        state.set_drops(self.drops);
+        for &program in &self.patterns {
+            state.add_pattern(PatternRunner::new(program));
+        }
+        if let Some(path) = &self.path {
+            state.set_path(path.clone());
+        }
+        state
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Wave {
+    pub time: f32,
+    pub enemies: Vec<EnemySpawn>,
+}
+
+// テンプレートを名前で引けるようにしたもの。継承はここで展開しておく
+struct TemplateBook {
+    templates: HashMap<String, TemplateDef>,
+}
+
+impl TemplateBook {
+    fn load(path: &str) -> Result<TemplateBook, Box<dyn Error>> {
+        TemplateBook::from_toml(&fs::read_to_string(path)?)
+    }
+
+    fn from_toml(text: &str) -> Result<TemplateBook, Box<dyn Error>> {
+        let file: TemplateFile = toml::from_str(text)?;
+        let mut templates = HashMap::new();
+        for def in file.template {
+            let name = def.name.clone();
+            if templates.insert(name.clone(), def).is_some() {
+                return Err(format!("duplicate template: {}", name).into());
+            }
+        }
+        Ok(TemplateBook {
+            templates: templates,
+        })
+    }
+
+    // name の設定を継承元までたどって合成する
+    fn resolve(&self, name: &str) -> Result<EnemyFields, String> {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut fields = EnemyFields::default();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                let chain = chain.join(" -> ");
+                return Err(format!("template inherits itself: {} -> {}", chain, name));
+            }
+            chain.push(name);
+            let def = self
+                .templates
+                .get(name)
+                .ok_or_else(|| format!("unknown template: {}", name))?;
+            fields = fields.inherit(&def.fields);
+            next = def.base.as_deref();
+        }
+        Ok(fields)
+    }
+}
+
+// ウェーブを時刻順に並べたステージ
+#[derive(Clone, Debug, Default)]
+pub struct Stage {
+    waves: Vec<Wave>,
+    backgrounds: Vec<LayerDef>,
+    bgm: Option<String>,
+}
+
+impl Stage {
+    pub fn new() -> Self {
+        Stage::default()
+    }
+
+    // テンプレートとステージを読み込み、参照しているテンプレート・パターン・経路・画像が
+    // すべて存在することを確かめる
+    pub fn load(
+        templates_path: &str,
+        stage_path: &str,
+        patterns: &PatternBook,
+        paths: &PathBook,
+        has_sprite: impl Fn(&str) -> bool,
+    ) -> Result<Stage, Box<dyn Error>> {
+        let templates = TemplateBook::load(templates_path)?;
+        for name in templates.templates.keys() {
+            templates.resolve(name)?;
+        }
+
+        let file: StageFile = toml::from_str(&fs::read_to_string(stage_path)?)?;
+        let mut waves = Vec::new();
+        for wave in file.wave {
+            let mut enemies = Vec::new();
+            for def in wave.enemies {
+                let SpawnDef {
+                    template,
+                    x,
+                    y,
+                    fields,
+                } = def;
+                let fields = fields.inherit(&templates.resolve(&template)?);
+                let spawn = resolve_spawn(Vector2(x, y), fields, patterns, paths)
+                    .map_err(|err| format!("{} at ({}, {}): {}", template, x, y, err))?;
+                if !has_sprite(&spawn.sprite) {
+                    return Err(format!("unknown sprite: {}", spawn.sprite).into());
+                }
+                enemies.push(spawn);
+            }
+            waves.push(Wave {
+                time: wave.time,
+                enemies: enemies,
+            });
+        }
+        waves.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for layer in &file.background {
+            if layer.fade < 0.0 {
+                return Err(
+                    format!("background {}: fade must not be negative", layer.image).into(),
+                );
+            }
+        }
+        Ok(Stage {
+            waves: waves,
+            backgrounds: file.background,
+            bgm: file.bgm,
+        })
+    }
+
+    pub fn get_waves(&self) -> &[Wave] {
+        &self.waves
+    }
+
+    pub fn get_backgrounds(&self) -> &[LayerDef] {
+        &self.backgrounds
+    }
+
+    pub fn get_bgm(&self) -> Option<&str> {
+        self.bgm.as_deref()
+    }
+}
+
+fn resolve_spawn(
+    x_y: Vector2,
+    fields: EnemyFields,
+    patterns: &PatternBook,
+    paths: &PathBook,
+) -> Result<EnemySpawn, String> {
+    let w = fields.w.ok_or("w is not set")?;
+    let h = fields.h.ok_or("h is not set")?;
+    let hp = fields.hp.ok_or("hp is not set")?;
+    let patterns = match &fields.pattern {
+        Some(name) => patterns.find_looping(name)?.to_vec(),
+        None => Vec::new(),
+    };
+    let path = match &fields.path {
+        Some(name) => Some(
+            paths
+                .find(name)
+                .ok_or_else(|| format!("unknown path: {}", name))?
+                .clone(),
+        ),
+        None => None,
+    };
+    Ok(EnemySpawn {
+        x_y: x_y,
+        w_h: Vector2(w, h),
+        hitbox: fields.hitbox,
+        hp: hp,
+        max_collision_timeout: fields
+            .max_collision_timeout
+            .unwrap_or(DEFAULT_COLLISION_TIMEOUT),
+        ang_vel: fields.ang_vel.unwrap_or(0.0),
+        vel: Vector2(fields.vx.unwrap_or(0.0), fields.vy.unwrap_or(0.0)),
+        sprite: fields.sprite.unwrap_or_else(|| DEFAULT_SPRITE.to_string()),
+        drops: fields.drops.unwrap_or(0),
+        patterns: patterns,
+        path: path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(text: &str) -> TemplateBook {
+        TemplateBook::from_toml(text).unwrap()
+    }
+
+    #[test]
+    fn resolve_fills_missing_fields_from_the_base() {
+        let book = book(
+            r#"
+            [[template]]
+            name = "small"
+            sprite = "enemy"
+            hp = 5
+            drops = 2
+            pattern = "aimed"
+
+            [[template]]
+            name = "small_tough"
+            base = "small"
+            hp = 20
+
+            [[template]]
+            name = "small_tough_ring"
+            base = "small_tough"
+            pattern = "ring"
+            "#,
+        );
+        let fields = book.resolve("small_tough_ring").unwrap();
+        // 近い方の設定が優先され、書かれていない項目は遠い継承元から受け継ぐ
+        assert_eq!(fields.pattern.as_deref(), Some("ring"));
+        assert_eq!(fields.hp, Some(20));
+        assert_eq!(fields.drops, Some(2));
+        assert_eq!(fields.sprite.as_deref(), Some("enemy"));
+        // どこにも書かれていない項目は None のまま
+        assert_eq!(fields.path, None);
+    }
+
+    #[test]
+    fn spawn_fields_override_the_template() {
+        let book = book(
+            r#"
+            [[template]]
+            name = "small"
+            hp = 5
+            drops = 2
+            "#,
+        );
+        let spawn = EnemyFields {
+            hp: Some(8),
+            ..EnemyFields::default()
+        };
+        let fields = spawn.inherit(&book.resolve("small").unwrap());
+        assert_eq!(fields.hp, Some(8));
+        assert_eq!(fields.drops, Some(2));
+    }
+
+    #[test]
+    fn inheritance_cycles_are_rejected() {
+        let book = book(
+            r#"
+            [[template]]
+            name = "a"
+            base = "b"
+
+            [[template]]
+            name = "b"
+            base = "c"
+
+            [[template]]
+            name = "c"
+            base = "a"
+
+            [[template]]
+            name = "self"
+            base = "self"
+            "#,
+        );
+        let err = book.resolve("a").unwrap_err();
+        assert!(err.contains("a -> b -> c -> a"), "{}", err);
+        assert!(book.resolve("self").is_err());
+    }
+
+    #[test]
+    fn unknown_or_duplicate_templates_are_errors() {
+        let book = book(
+            r#"
+            [[template]]
+            name = "orphan"
+            base = "missing"
+            "#,
+        );
+        assert!(book.resolve("orphan").is_err());
+        assert!(book.resolve("missing").is_err());
+
+        let duplicate = TemplateBook::from_toml(
+            r#"
+            [[template]]
+            name = "small"
+
+            [[template]]
+            name = "small"
+            "#,
+        );
+        assert!(duplicate.is_err());
+    }
+}
@@ -18,6 +18,21 @@ impl Vector2 {
         self.0.powi(2) + self.1.powi(2)
     }
 
+    pub fn dot(self, other: Vector2) -> f32 {
+        self.0 * other.0 + self.1 * other.1
+    }
+
+    // vec_from_angle の逆変換
+    pub fn angle(self) -> f32 {
+        self.0.atan2(self.1)
+    }
+
+    // 時計回りに angle だけ回す
+    pub fn rotated(self, angle: f32) -> Vector2 {
+        let (sin, cos) = angle.sin_cos();
+        Vector2(self.0 * cos + self.1 * sin, self.1 * cos - self.0 * sin)
+    }
+
     pub fn world_to_screen_coords(self, screen_w_h: (f32, f32)) -> Vector2 {
         let x = self.0 + screen_w_h.0 / 2.0;
         let y = screen_w_h.1 - (self.1 + screen_w_h.1 / 2.0);
@@ -99,3 +114,9 @@ pub fn vec_from_angle(angle: f32) -> Vector2 {
     let vy = angle.cos();
     Vector2(vx, vy)
 }
+
+// [-PI, PI) に収める
+pub fn normalize_angle(angle: f32) -> f32 {
+    let two_pi = 2.0 * std::f32::consts::PI;
+    (angle + std::f32::consts::PI).rem_euclid(two_pi) - std::f32::consts::PI
+}
@@ -0,0 +1,79 @@
+extern crate my_first_ggez;
+
+use my_first_ggez::actor_mods::actor_state::ActorState;
+use my_first_ggez::actor_mods::boss::Boss;
+use my_first_ggez::path_mods::path::PathBook;
+use my_first_ggez::pattern_mods::bulletml;
+use my_first_ggez::pattern_mods::pattern::PatternBook;
+use my_first_ggez::render_mods::layout::PLAYFIELD_W_H;
+
+// data/bosses.toml のボスを弾を撃たせずに動かし、フェーズごとの経路を確かめる
+const FRAME: f32 = 1.0 / 60.0;
+
+fn load_boss() -> Boss {
+    let mut patterns = PatternBook::load("./data/patterns.toml").unwrap();
+    bulletml::import_dir(&mut patterns, "./data/bulletml").unwrap();
+    let paths = PathBook::load("./data/paths.toml").unwrap();
+    Boss::load("./data/bosses.toml", &patterns, &paths).unwrap()
+}
+
+#[test]
+fn boss_stays_inside_the_field_until_each_phase_times_out() {
+    // 一度も撃たれなければ、どのフェーズも制限時間いっぱいまで続く
+    let mut boss = load_boss();
+    let mut state = boss.spawn(1.0);
+    let mut phases_ended = 0;
+    // 全フェーズの制限時間の合計より長く回す
+    for _ in 0..(200.0 / FRAME) as u32 {
+        if boss.is_defeated() {
+            break;
+        }
+        state.update(FRAME, PLAYFIELD_W_H);
+        state.clear_dead_stuff(PLAYFIELD_W_H);
+        let life = state.get_actor().get_life();
+        let inside = state.get_actor().inside_window(PLAYFIELD_W_H);
+        if boss.update(&mut state, FRAME).is_some() {
+            phases_ended += 1;
+            assert!(inside, "boss left the field in phase {}", phases_ended);
+            assert!(life > 0, "boss was despawned in phase {}", phases_ended);
+        }
+    }
+    assert_eq!(phases_ended, 3);
+}
+
+// 最初のフェーズを終わらせ、スペルカードのある 2 番目のフェーズへ進める
+fn enter_card_phase(boss: &mut Boss, state: &mut ActorState) {
+    state.get_mut_actor().consume_all();
+    assert_eq!(boss.update(state, FRAME), Some(0));
+}
+
+#[test]
+fn breaking_a_card_pays_its_bonus() {
+    let mut boss = load_boss();
+    let mut state = boss.spawn(1.0);
+    enter_card_phase(&mut boss, &mut state);
+    state.get_mut_actor().consume_all();
+    boss.break_phase();
+    assert_eq!(boss.update(&mut state, FRAME), Some(5000));
+}
+
+#[test]
+fn despawning_a_card_pays_no_bonus() {
+    // 撃たれずに画面外で消えたときは、体力が 0 でも取得にならない
+    let mut boss = load_boss();
+    let mut state = boss.spawn(1.0);
+    enter_card_phase(&mut boss, &mut state);
+    state.get_mut_actor().consume_all();
+    assert_eq!(boss.update(&mut state, FRAME), Some(0));
+}
+
+#[test]
+fn failing_a_card_pays_no_bonus() {
+    let mut boss = load_boss();
+    let mut state = boss.spawn(1.0);
+    enter_card_phase(&mut boss, &mut state);
+    boss.fail_card();
+    state.get_mut_actor().consume_all();
+    boss.break_phase();
+    assert_eq!(boss.update(&mut state, FRAME), Some(0));
+}
@@ -0,0 +1,118 @@
+extern crate my_first_ggez;
+
+use my_first_ggez::actor_mods::actor::*;
+use my_first_ggez::actor_mods::actor_state::ActorState;
+use my_first_ggez::pattern_mods::bulletml;
+use my_first_ggez::pattern_mods::pattern::PatternBook;
+use my_first_ggez::pattern_mods::pattern_runner::PatternRunner;
+use my_first_ggez::vector2::Vector2;
+
+use oorandom::Rand32;
+
+// data/bulletml のパターンを画面なしで動かし、XML から手で求めた結果と比べる
+// 敵は (0, 150)、自機は create_player の (0, -180) に止めて置く
+// BulletML の速さは 1 フレームあたりの px で、60 フレームで 1 秒
+const BULLETML_DIR: &str = "./data/bulletml";
+const FRAME: f32 = 1.0 / 60.0;
+// 弾が画面外に出て消えないよう十分に広くとる
+const AREA_W_H: Vector2 = Vector2(4000.0, 4000.0);
+const ENEMY_X_Y: Vector2 = Vector2(0.0, 150.0);
+
+fn load_book() -> PatternBook {
+    let mut book = PatternBook::new();
+    bulletml::import_dir(&mut book, BULLETML_DIR).unwrap();
+    book
+}
+
+// pattern を frames フレーム動かし、残っている弾の位置を撃った順に返す
+fn simulate(pattern: &str, rank: f32, frames: u32) -> Vec<Vector2> {
+    let book = load_book();
+    let programs = book.find_looping(pattern).unwrap();
+    let enemy = Actor::new(
+        ActorType::Enemy,
+        ENEMY_X_Y,
+        Vector2(16.0, 16.0),
+        0.0,
+        Vector2(0.0, 0.0),
+        0.0,
+        1,
+        0.0,
+    );
+    let mut state = ActorState::new(enemy);
+    for &program in programs {
+        state.add_pattern(PatternRunner::new(program));
+    }
+
+    let player = create_player();
+    let mut rng = Rand32::new(0);
+    for _ in 0..frames {
+        state.run_pattern(&book, FRAME, rank, &player, &mut rng);
+        state.run_shot_patterns(&book, FRAME, rank, &player, &mut rng);
+        state.update(FRAME, AREA_W_H);
+        state.clear_dead_stuff(AREA_W_H);
+    }
+    state
+        .get_shots()
+        .iter()
+        .map(|shot| shot.get_x_y())
+        .collect()
+}
+
+fn assert_near(actual: Vector2, expected: Vector2, tolerance: f32) {
+    assert!(
+        (actual - expected).norm() <= tolerance,
+        "expected ({}, {}), got ({}, {})",
+        expected.0,
+        expected.1,
+        actual.0,
+        actual.1
+    );
+}
+
+#[test]
+fn aimed_burst_fires_ten_shots_at_the_player() {
+    // 10 回「自機狙いで速さ 2 の弾を撃ち、10 フレーム待つ」ので、撃つのは 0, 10, ..., 90 フレーム目
+    // 95 フレーム後には 10 発すべてが出ている
+    let shots = simulate("aimed_burst", 0.5, 95);
+    assert_eq!(shots.len(), 10);
+    // 自機は真下にいるので、最初の弾は 0 フレーム目から真下へ 2 px ずつ進む
+    // 150 - 2 * 95 = -40。撃ったフレームに動くかどうかの 1 フレーム分は許す
+    assert_near(shots[0], Vector2(0.0, -40.0), 2.0);
+    // 最後の弾は 90 フレーム目に撃たれ、150 - 2 * 5 = 140 のあたりにいる
+    assert_near(shots[9], Vector2(0.0, 140.0), 2.0);
+}
+
+#[test]
+fn stop_and_go_stops_before_turning() {
+    // 右 (90 度) へ速さ 1 で撃たれ、60 フレーム進んでから 1 フレームかけて止まる
+    // x は 60 から 61 のあたりで止まり、さらに 60 フレーム待つので 100 フレーム目はまだ止まっている
+    let shots = simulate("stop_and_go", 0.5, 100);
+    assert_eq!(shots.len(), 1);
+    assert_near(shots[0], Vector2(61.0, 150.0), 2.0);
+}
+
+#[test]
+fn stop_and_go_turns_down_after_waiting() {
+    // 止まってから 60 フレーム待った後、下 (180 度) へ向きを変え 30 フレームかけて速さ 2 になる
+    // 121 フレーム目から動き出すので、150 フレーム目には止まった位置より下にいる
+    let shots = simulate("stop_and_go", 0.5, 150);
+    assert_eq!(shots.len(), 1);
+    assert!((shots[0].0 - 61.0).abs() <= 2.0);
+    assert!(shots[0].1 < 150.0 - 10.0);
+}
+
+#[test]
+fn split_replaces_the_parent_with_eight_shots() {
+    // 親弾は 30 フレーム後に 45 度ずつ 8 発撃って消える
+    let shots = simulate("split", 0.5, 60);
+    assert_eq!(shots.len(), 8);
+}
+
+#[test]
+fn rank_params_scales_shot_count_with_rank() {
+    // 4 + rank * 4 回、5 フレームおきに撃つ
+    // rank 0.5 なら 6 発で、最後は 25 フレーム目
+    assert_eq!(simulate("rank_params", 0.5, 29).len(), 6);
+    // rank 1.0 なら 8 発で、最後は 35 フレーム目
+    assert_eq!(simulate("rank_params", 1.0, 39).len(), 8);
+}